@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Aggregate, AggregateFunction},
+    custom_error::CustomError,
+    database::{Record, Value},
+};
+
+impl Aggregate {
+    pub fn result_type(&self, fields: &HashMap<String, String>) -> Result<String, CustomError> {
+        let Some(field) = &self.field else {
+            return Ok("Int".to_string());
+        };
+        let field_type = fields
+            .get(field)
+            .ok_or_else(|| CustomError::UnknownField(field.to_string()))?;
+        let numeric = matches!(
+            field_type.trim().to_ascii_uppercase().as_str(),
+            "INT" | "FLOAT"
+        );
+
+        match self.function {
+            AggregateFunction::Count => Ok("Int".to_string()),
+            AggregateFunction::Sum | AggregateFunction::Avg if !numeric => {
+                Err(CustomError::InvalidAggregate(format!(
+                    "{} requires a numeric field, {} is {}",
+                    self.function.as_str(),
+                    field,
+                    field_type
+                )))
+            }
+            AggregateFunction::Sum => Ok(field_type.to_string()),
+            AggregateFunction::Avg => Ok("Float".to_string()),
+            AggregateFunction::Min | AggregateFunction::Max => Ok(field_type.to_string()),
+        }
+    }
+
+    // Returns None when there is nothing to aggregate, e.g. SUM over an empty group, and
+    // when a SUM of Ints overflows, like arithmetic does.
+    pub fn evaluate(&self, records: &[&Record]) -> Option<Value> {
+        let values = match &self.field {
+            Some(field) => records
+                .iter()
+                .filter_map(|r| r.values.get(field))
+                .collect::<Vec<_>>(),
+            None => return Some(Value::Int(records.len() as i64)),
+        };
+
+        match self.function {
+            AggregateFunction::Count => Some(Value::Int(values.len() as i64)),
+            AggregateFunction::Sum => {
+                let first = values.first()?;
+                let result = match first {
+                    Value::Int(_) => {
+                        Value::Int(values.iter().try_fold(0i64, |sum, v| match v {
+                            Value::Int(i) => sum.checked_add(*i),
+                            _ => Some(sum),
+                        })?)
+                    }
+                    _ => Value::Float(values.iter().filter_map(|v| v.as_f64()).sum()),
+                };
+                Some(result)
+            }
+            AggregateFunction::Avg => {
+                if values.is_empty() {
+                    return None;
+                }
+                let sum: f64 = values.iter().filter_map(|v| v.as_f64()).sum();
+                Some(Value::Float(sum / values.len() as f64))
+            }
+            AggregateFunction::Min => values.into_iter().min_by(|a, b| a.compare(b)).cloned(),
+            AggregateFunction::Max => values.into_iter().max_by(|a, b| a.compare(b)).cloned(),
+        }
+    }
+}
+
+// Groups come out ordered by their GROUP BY values.
+pub fn group_records(
+    mut records: Vec<&Record>,
+    group_by: &[String],
+    aggregates: &[Aggregate],
+) -> Vec<Record> {
+    let group_of = |r: &Record| {
+        group_by
+            .iter()
+            .map(|field| r.values.get(field).cloned())
+            .collect::<Vec<_>>()
+    };
+    records.sort_by_cached_key(|r| group_of(r));
+
+    let groups = if group_by.is_empty() {
+        vec![records.as_slice()]
+    } else {
+        records
+            .chunk_by(|a, b| group_of(a) == group_of(b))
+            .collect::<Vec<_>>()
+    };
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut record = Record::new();
+            if let Some(first) = group.first() {
+                for field in group_by {
+                    if let Some(value) = first.values.get(field) {
+                        record.values.insert(field.to_string(), value.clone());
+                    }
+                }
+            }
+            for aggregate in aggregates {
+                if let Some(value) = aggregate.evaluate(group) {
+                    record.values.insert(aggregate.to_string(), value);
+                }
+            }
+            record
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        ast::{Aggregate, AggregateFunction},
+        database::{Record, Value},
+    };
+
+    fn records(values: &[i64]) -> Vec<Record> {
+        values
+            .iter()
+            .map(|n| Record::with_values(HashMap::from([("n".to_string(), Value::Int(*n))])))
+            .collect()
+    }
+
+    fn sum(records: &[Record]) -> Option<Value> {
+        let aggregate = Aggregate {
+            function: AggregateFunction::Sum,
+            field: Some("n".to_string()),
+        };
+        aggregate.evaluate(&records.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn int_sum_adds_values() {
+        assert_eq!(sum(&records(&[1, 2, 3])), Some(Value::Int(6)));
+        assert_eq!(sum(&records(&[])), None);
+    }
+
+    #[test]
+    fn int_sum_overflow_is_null() {
+        assert_eq!(sum(&records(&[i64::MAX, 1])), None);
+        assert_eq!(sum(&records(&[i64::MAX, 1, -1])), None);
+    }
+}
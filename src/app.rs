@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::{
     command_history::CommandHistory, custom_error::CustomError, database::AnyDatabase,
-    handlers::handle_input_any_db,
+    handlers::handle_input_any_db, lexer::quote,
 };
 #[derive(Debug, PartialEq)]
 pub enum CurrentScreen {
@@ -40,6 +40,8 @@ pub enum CurrentCommand {
     Delete,
     SaveAs,
     ReadFrom,
+    Drop,
+    Truncate,
 }
 
 pub struct App {
@@ -160,7 +162,7 @@ impl App {
                 let fields = self
                     .generic_hashmap
                     .iter()
-                    .map(|(k, v)| format!("{} = {}", k, v))
+                    .map(|(k, v)| format!("{} = {}", k, value_text(v, &[])))
                     .collect::<Vec<_>>()
                     .join(", ");
 
@@ -189,7 +191,11 @@ impl App {
                 self.current_screen = CurrentScreen::SelectCondition;
             }
             (CurrentCommand::Select, CurrentScreen::SelectCondition) => {
-                let fields = self.selected_fields.join(", ");
+                let fields = if self.selected_fields.is_empty() {
+                    "*".to_string()
+                } else {
+                    self.selected_fields.join(", ")
+                };
 
                 let table = match &self.input_table_name {
                     Some(v) => v.as_str(),
@@ -212,8 +218,9 @@ impl App {
 
                 let mut input = format!("SELECT {} FROM {}", fields, table);
 
-                if !c0.is_empty() && !c1.is_empty() && !c2.is_empty() {
-                    input = format!("{} WHERE {} {} {}", input, c0, c1, c2);
+                let condition = condition_text(c0, &c1, c2, &self.possibilities);
+                if !c0.is_empty() && !condition.is_empty() {
+                    input = format!("{} WHERE {}", input, condition);
                 }
 
                 match handle_input_any_db(input, &mut self.database, &mut self.history) {
@@ -242,6 +249,32 @@ impl App {
                 }
                 self.current_screen = CurrentScreen::Results;
             }
+            (CurrentCommand::Drop, CurrentScreen::SelectTable) => {
+                let table = match &self.input_table_name {
+                    Some(v) => v.as_str(),
+                    None => "",
+                };
+                let input = format!("DROP TABLE {}", table);
+
+                match handle_input_any_db(input, &mut self.database, &mut self.history) {
+                    Ok(res) => self.result = res,
+                    Err(e) => self.result = e.to_string(),
+                }
+                self.current_screen = CurrentScreen::Results;
+            }
+            (CurrentCommand::Truncate, CurrentScreen::SelectTable) => {
+                let table = match &self.input_table_name {
+                    Some(v) => v.as_str(),
+                    None => "",
+                };
+                let input = format!("TRUNCATE TABLE {}", table);
+
+                match handle_input_any_db(input, &mut self.database, &mut self.history) {
+                    Ok(res) => self.result = res,
+                    Err(e) => self.result = e.to_string(),
+                }
+                self.current_screen = CurrentScreen::Results;
+            }
             (CurrentCommand::SaveAs, CurrentScreen::InputFilePath) => {
                 let path = match &self.file_path {
                     Some(v) => v.as_str(),
@@ -291,6 +324,8 @@ impl App {
             3 => CurrentCommand::Delete,
             4 => CurrentCommand::SaveAs,
             5 => CurrentCommand::ReadFrom,
+            6 => CurrentCommand::Drop,
+            7 => CurrentCommand::Truncate,
             _ => CurrentCommand::None,
         };
         self.current_index = 0;
@@ -302,7 +337,138 @@ impl App {
             CurrentCommand::Delete => CurrentScreen::SelectTable,
             CurrentCommand::SaveAs => CurrentScreen::InputFilePath,
             CurrentCommand::ReadFrom => CurrentScreen::InputFilePath,
+            CurrentCommand::Drop => CurrentScreen::SelectTable,
+            CurrentCommand::Truncate => CurrentScreen::SelectTable,
             _ => CurrentScreen::Main,
         };
     }
 }
+
+// Typed text is a value to be quoted unless it already is, or is a number, TRUE, FALSE
+// or the name of a field, as in `pages > year`.
+fn value_text(value: &str, fields: &[String]) -> String {
+    let value = value.trim();
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let number = digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.parse::<f64>().is_ok();
+    let unquoted = value.starts_with(['\'', '"'])
+        || number
+        || value.eq_ignore_ascii_case("true")
+        || value.eq_ignore_ascii_case("false")
+        || fields.iter().any(|f| f == value);
+    match unquoted {
+        true => value.to_string(),
+        false => quote(value),
+    }
+}
+
+// The WHERE condition built from the field, operator and text picked on the
+// SelectCondition screen. IN takes a comma separated list and BETWEEN two values
+// joined by AND, each quoted on its own.
+fn condition_text(field: &str, op: &str, value: &str, fields: &[String]) -> String {
+    let value = value.trim();
+    match op {
+        "IS NULL" | "IS NOT NULL" => format!("{} {}", field, op),
+        _ if value.is_empty() => String::new(),
+        "LIKE" | "NOT LIKE" | "ILIKE" | "NOT ILIKE" | "~" | "NOT ~"
+            if !value.starts_with(['\'', '"']) =>
+        {
+            format!("{} {} {}", field, op, quote(value))
+        }
+        "IN" | "NOT IN" if value.starts_with('(') => format!("{} {} {}", field, op, value),
+        "IN" | "NOT IN" => {
+            let values = value
+                .split(',')
+                .map(|v| value_text(v, fields))
+                .collect::<Vec<_>>();
+            format!("{} {} ({})", field, op, values.join(", "))
+        }
+        "BETWEEN" | "NOT BETWEEN" => {
+            let words = value.split_whitespace().collect::<Vec<_>>();
+            match words.iter().position(|w| w.eq_ignore_ascii_case("and")) {
+                Some(and) => format!(
+                    "{} {} {} AND {}",
+                    field,
+                    op,
+                    value_text(&words[..and].join(" "), fields),
+                    value_text(&words[and + 1..].join(" "), fields)
+                ),
+                None => format!("{} {} {}", field, op, value),
+            }
+        }
+        _ => format!("{} {} {}", field, op, value_text(value, fields)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{condition::Op, test_util::database, test_util::run};
+
+    use super::{condition_text, value_text};
+
+    #[test]
+    fn every_listed_operator_builds_a_condition_that_runs() {
+        let mut database = database(&[
+            "CREATE t KEY id FIELDS id: Int, s: String, n: Int",
+            "INSERT INTO t VALUES (1, 'ab', 10), (2, 'cd', 20), (3, 'it''s', NULL)",
+        ]);
+        let fields = ["id", "s", "n"].map(String::from);
+        let typed = |op: &str| match op {
+            "LIKE" | "NOT LIKE" | "ILIKE" | "NOT ILIKE" => "A%",
+            "~" | "NOT ~" => "^a",
+            "IN" | "NOT IN" => "ab, it's",
+            "BETWEEN" | "NOT BETWEEN" => "ab and cd",
+            "=" | "!=" => "it's",
+            _ => "n",
+        };
+        for op in Op::get_options() {
+            let field = match op.as_str() {
+                "<" | "<=" | ">" | ">=" => "id",
+                _ => "s",
+            };
+            let condition = condition_text(field, &op, typed(&op), &fields);
+            let result = run(
+                &mut database,
+                &format!("SELECT id FROM t WHERE {}", condition),
+            );
+            assert!(result.is_ok(), "{}: {:?}", condition, result);
+        }
+    }
+
+    #[test]
+    fn numbers_and_bools_stay_bare() {
+        for (typed, text) in [
+            ("5", "5"),
+            ("-2.5", "-2.5"),
+            ("TRUE", "TRUE"),
+            ("'x'", "'x'"),
+            ("Rome", "\"Rome\""),
+            ("1e5", "\"1e5\""),
+            ("inf", "\"inf\""),
+            ("it's", "\"it's\""),
+        ] {
+            assert_eq!(value_text(typed, &[]), text);
+        }
+    }
+
+    #[test]
+    fn values_are_quoted_one_by_one() {
+        let fields = ["id", "n"].map(String::from);
+        assert_eq!(
+            condition_text("n", "BETWEEN", "1 AND 50", &fields),
+            "n BETWEEN 1 AND 50"
+        );
+        assert_eq!(
+            condition_text("s", "NOT BETWEEN", "a b and 'c'", &fields),
+            "s NOT BETWEEN \"a b\" AND 'c'"
+        );
+        assert_eq!(
+            condition_text("s", "IN", "x, 2, n", &fields),
+            "s IN (\"x\", 2, n)"
+        );
+        assert_eq!(condition_text("n", ">", "id", &fields), "n > id");
+        assert_eq!(condition_text("s", "=", "id x", &fields), "s = \"id x\"");
+        assert_eq!(condition_text("s", "LIKE", "5%", &fields), "s LIKE \"5%\"");
+    }
+}
@@ -0,0 +1,1463 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{condition::Op, custom_error::CustomError, database::Value, lexer::quote};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Number(String),
+    Quoted(String),
+    Word(String),
+    // `$1` in a prepared statement, replaced by its value before the statement runs.
+    Parameter(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub field: String,
+    pub value: Literal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Field(String),
+    Aggregate(Aggregate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalarFunction {
+    Upper,
+    Lower,
+    Length,
+    Abs,
+    Round,
+    Coalesce,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(Column),
+    Literal(Literal),
+    Null,
+    Negate(Box<Operand>),
+    Arithmetic {
+        op: ArithmeticOp,
+        left: Box<Operand>,
+        right: Box<Operand>,
+    },
+    Function {
+        function: ScalarFunction,
+        args: Vec<Operand>,
+    },
+    Case(Box<Case>),
+    Subquery(Box<Subquery>),
+    Window(Box<Window>),
+}
+
+// Subqueries cannot refer to the outer row, so each one runs once, before the statement
+// around it is bound, and its rows are kept in `result`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subquery {
+    pub query: Query,
+    pub result: Option<SubqueryResult>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubqueryResult {
+    pub types: Vec<String>,
+    pub rows: Vec<Vec<Option<Value>>>,
+}
+
+// `CASE x WHEN 1 THEN ...` compares `x` with each WHEN value; without a subject every
+// WHEN is a condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub subject: Option<Operand>,
+    pub branches: Vec<(CaseWhen, Operand)>,
+    pub otherwise: Option<Operand>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaseWhen {
+    Value(Operand),
+    Condition(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFunction {
+    RowNumber,
+    Rank,
+    DenseRank,
+    Lag,
+    Lead,
+    // `SUM(pages) OVER (...)` aggregates the window frame rather than a group.
+    Aggregate(Aggregate),
+}
+
+// Computed once the rows are filtered and grouped, and stored in each row under its
+// text, the way aggregates are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub function: WindowFunction,
+    pub args: Vec<Operand>,
+    pub partition_by: Vec<Operand>,
+    pub order_by: Vec<OrderBy>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    Wildcard(Option<String>),
+    Operand {
+        operand: Operand,
+        alias: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub left: Operand,
+    pub op: Op,
+    pub values: Vec<Operand>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Comparison(Comparison),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Box<Subquery>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub operand: Operand,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateStatement {
+    pub table_name: String,
+    pub key_field: String,
+    pub fields: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InsertStatement {
+    pub table_name: String,
+    // Empty means every column of the table, in order.
+    pub columns: Vec<String>,
+    pub rows: InsertRows,
+    // Only the key field can conflict, so a target other than it is rejected.
+    pub conflict_target: Option<String>,
+    pub on_conflict: OnConflict,
+}
+
+// `INSERT f = v, ... INTO t` is read as a single row of VALUES.
+#[derive(Debug, Clone)]
+pub enum InsertRows {
+    // None is NULL, which leaves the field out of the record.
+    Values(Vec<Vec<Option<Literal>>>),
+    Query(Query),
+}
+
+// What happens to a row whose key is already taken.
+#[derive(Debug, Clone)]
+pub enum OnConflict {
+    Fail,
+    Nothing,
+    // `excluded.f` reads f from the row that was being inserted.
+    Update(Vec<(String, Operand)>),
+    // REPLACE INTO: the new row takes the place of the old one.
+    Replace,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeleteStatement {
+    pub table_name: String,
+    // `DELETE key FROM t` removes that one record, and fails if there is none.
+    pub key: Option<Literal>,
+    pub condition: Option<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table_name: String,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStatement {
+    pub distinct: bool,
+    pub table_name: String,
+    // `FROM (SELECT ...) AS t` reads the rows of this query, named `table_name`.
+    pub derived: Option<Box<Query>>,
+    pub joins: Vec<Join>,
+    pub fields: Vec<Projection>,
+    pub condition: Option<Expr>,
+    pub group_by: Vec<String>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Select(Box<SelectStatement>),
+    Compound(Box<CompoundQuery>),
+    With(Box<WithQuery>),
+}
+
+// ORDER BY and LIMIT written after the last SELECT apply to the whole compound query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundQuery {
+    pub operator: SetOperator,
+    pub all: bool,
+    pub left: Query,
+    pub right: Query,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+// Each CTE can read the ones before it; under RECURSIVE a UNION may also read itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithQuery {
+    pub recursive: bool,
+    pub ctes: Vec<CommonTableExpression>,
+    pub body: Query,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonTableExpression {
+    pub name: String,
+    // Renames the query's columns by position when not empty.
+    pub columns: Vec<String>,
+    pub query: Query,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateStatement {
+    pub table_name: String,
+    pub assignments: Vec<Assignment>,
+    pub condition: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DropStatement {
+    pub table_name: String,
+    pub if_exists: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TruncateStatement {
+    pub table_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum AlterAction {
+    AddColumn {
+        name: String,
+        typ: String,
+        default: Option<Literal>,
+    },
+    DropColumn(String),
+    RenameColumn {
+        from: String,
+        to: String,
+    },
+    RenameTo(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct AlterStatement {
+    pub table_name: String,
+    pub action: AlterAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Create(CreateStatement),
+    Insert(InsertStatement),
+    Delete(DeleteStatement),
+    Select(Query),
+    Update(UpdateStatement),
+    Drop(DropStatement),
+    Truncate(TruncateStatement),
+    Alter(AlterStatement),
+    SaveAs(String),
+    ReadFrom(String),
+    Prepare {
+        name: String,
+        statement: Box<Statement>,
+    },
+    Execute {
+        name: String,
+        arguments: Vec<Literal>,
+    },
+    Explain {
+        analyze: bool,
+        query: Query,
+    },
+}
+
+impl Literal {
+    pub fn text(&self) -> &str {
+        match self {
+            Literal::Number(s) | Literal::Quoted(s) | Literal::Word(s) => s,
+            Literal::Parameter(_) => "",
+        }
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Quoted(s) => write!(f, "{}", quote(s)),
+            Literal::Number(s) | Literal::Word(s) => write!(f, "{}", s),
+            Literal::Parameter(n) => write!(f, "${}", n),
+        }
+    }
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "COUNT" => Some(AggregateFunction::Count),
+            "SUM" => Some(AggregateFunction::Sum),
+            "AVG" => Some(AggregateFunction::Avg),
+            "MIN" => Some(AggregateFunction::Min),
+            "MAX" => Some(AggregateFunction::Max),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+        }
+    }
+}
+
+impl Column {
+    pub fn name(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<Column, CustomError> {
+        match self {
+            Column::Field(name) => Ok(Column::Field(f(name)?)),
+            Column::Aggregate(a) => Ok(Column::Aggregate(Aggregate {
+                function: a.function,
+                field: a.field.as_deref().map(f).transpose()?,
+            })),
+        }
+    }
+}
+
+impl ArithmeticOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            ArithmeticOp::Add | ArithmeticOp::Subtract => 1,
+            ArithmeticOp::Multiply | ArithmeticOp::Divide => 2,
+        }
+    }
+}
+
+impl ScalarFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "UPPER" => Some(ScalarFunction::Upper),
+            "LOWER" => Some(ScalarFunction::Lower),
+            "LENGTH" => Some(ScalarFunction::Length),
+            "ABS" => Some(ScalarFunction::Abs),
+            "ROUND" => Some(ScalarFunction::Round),
+            "COALESCE" => Some(ScalarFunction::Coalesce),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScalarFunction::Upper => "UPPER",
+            ScalarFunction::Lower => "LOWER",
+            ScalarFunction::Length => "LENGTH",
+            ScalarFunction::Abs => "ABS",
+            ScalarFunction::Round => "ROUND",
+            ScalarFunction::Coalesce => "COALESCE",
+        }
+    }
+}
+
+impl WindowFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "ROW_NUMBER" => Some(WindowFunction::RowNumber),
+            "RANK" => Some(WindowFunction::Rank),
+            "DENSE_RANK" => Some(WindowFunction::DenseRank),
+            "LAG" => Some(WindowFunction::Lag),
+            "LEAD" => Some(WindowFunction::Lead),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowFunction::RowNumber => "ROW_NUMBER",
+            WindowFunction::Rank => "RANK",
+            WindowFunction::DenseRank => "DENSE_RANK",
+            WindowFunction::Lag => "LAG",
+            WindowFunction::Lead => "LEAD",
+            WindowFunction::Aggregate(a) => a.function.as_str(),
+        }
+    }
+}
+
+impl Window {
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<Window, CustomError> {
+        let map_all = |operands: &[Operand]| {
+            operands
+                .iter()
+                .map(|o| o.map_fields(f))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let function = match &self.function {
+            WindowFunction::Aggregate(a) => WindowFunction::Aggregate(Aggregate {
+                function: a.function,
+                field: a.field.as_deref().map(f).transpose()?,
+            }),
+            function => function.clone(),
+        };
+        Ok(Window {
+            function,
+            args: map_all(&self.args)?,
+            partition_by: map_all(&self.partition_by)?,
+            order_by: self
+                .order_by
+                .iter()
+                .map(|o| {
+                    Ok(OrderBy {
+                        operand: o.operand.map_fields(f)?,
+                        descending: o.descending,
+                    })
+                })
+                .collect::<Result<_, CustomError>>()?,
+        })
+    }
+}
+
+impl Operand {
+    pub fn name(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<Operand, CustomError> {
+        let operand = match self {
+            Operand::Column(c) => Operand::Column(c.map_fields(f)?),
+            Operand::Literal(l) => Operand::Literal(l.clone()),
+            Operand::Null => Operand::Null,
+            Operand::Negate(o) => Operand::Negate(Box::new(o.map_fields(f)?)),
+            Operand::Arithmetic { op, left, right } => Operand::Arithmetic {
+                op: *op,
+                left: Box::new(left.map_fields(f)?),
+                right: Box::new(right.map_fields(f)?),
+            },
+            Operand::Function { function, args } => Operand::Function {
+                function: *function,
+                args: args
+                    .iter()
+                    .map(|a| a.map_fields(f))
+                    .collect::<Result<_, _>>()?,
+            },
+            Operand::Case(case) => Operand::Case(Box::new(Case {
+                subject: case.subject.as_ref().map(|s| s.map_fields(f)).transpose()?,
+                branches: case
+                    .branches
+                    .iter()
+                    .map(|(when, then)| {
+                        let when = match when {
+                            CaseWhen::Value(v) => CaseWhen::Value(v.map_fields(f)?),
+                            CaseWhen::Condition(e) => CaseWhen::Condition(e.map_fields(f)?),
+                        };
+                        Ok((when, then.map_fields(f)?))
+                    })
+                    .collect::<Result<_, CustomError>>()?,
+                otherwise: case
+                    .otherwise
+                    .as_ref()
+                    .map(|o| o.map_fields(f))
+                    .transpose()?,
+            })),
+            // A subquery has its own FROM, so its names are not ours to resolve.
+            Operand::Subquery(s) => Operand::Subquery(s.clone()),
+            Operand::Window(w) => Operand::Window(Box::new(w.map_fields(f)?)),
+        };
+        Ok(operand)
+    }
+
+    pub fn for_each_subquery(
+        &mut self,
+        f: &mut impl FnMut(&mut Subquery) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Operand::Column(_) | Operand::Literal(_) | Operand::Null => Ok(()),
+            Operand::Negate(o) => o.for_each_subquery(f),
+            Operand::Arithmetic { left, right, .. } => {
+                left.for_each_subquery(f)?;
+                right.for_each_subquery(f)
+            }
+            Operand::Function { args, .. } => {
+                args.iter_mut().try_for_each(|a| a.for_each_subquery(f))
+            }
+            Operand::Case(case) => {
+                for operand in case.subject.iter_mut() {
+                    operand.for_each_subquery(f)?;
+                }
+                for (when, then) in case.branches.iter_mut() {
+                    match when {
+                        CaseWhen::Value(v) => v.for_each_subquery(f)?,
+                        CaseWhen::Condition(e) => e.for_each_subquery(f)?,
+                    }
+                    then.for_each_subquery(f)?;
+                }
+                for operand in case.otherwise.iter_mut() {
+                    operand.for_each_subquery(f)?;
+                }
+                Ok(())
+            }
+            Operand::Subquery(s) => f(s),
+            Operand::Window(w) => {
+                let operands = w.args.iter_mut().chain(w.partition_by.iter_mut());
+                for operand in operands.chain(w.order_by.iter_mut().map(|o| &mut o.operand)) {
+                    operand.for_each_subquery(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn for_each_literal(
+        &mut self,
+        f: &mut impl FnMut(&mut Literal) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Operand::Column(_) | Operand::Null => Ok(()),
+            Operand::Literal(l) => f(l),
+            Operand::Negate(o) => o.for_each_literal(f),
+            Operand::Arithmetic { left, right, .. } => {
+                left.for_each_literal(f)?;
+                right.for_each_literal(f)
+            }
+            Operand::Function { args, .. } => {
+                args.iter_mut().try_for_each(|a| a.for_each_literal(f))
+            }
+            Operand::Case(case) => {
+                for operand in case.subject.iter_mut() {
+                    operand.for_each_literal(f)?;
+                }
+                for (when, then) in case.branches.iter_mut() {
+                    match when {
+                        CaseWhen::Value(v) => v.for_each_literal(f)?,
+                        CaseWhen::Condition(e) => e.for_each_literal(f)?,
+                    }
+                    then.for_each_literal(f)?;
+                }
+                for operand in case.otherwise.iter_mut() {
+                    operand.for_each_literal(f)?;
+                }
+                Ok(())
+            }
+            Operand::Subquery(s) => s.query.for_each_literal(f),
+            Operand::Window(w) => {
+                let operands = w.args.iter_mut().chain(w.partition_by.iter_mut());
+                for operand in operands.chain(w.order_by.iter_mut().map(|o| &mut o.operand)) {
+                    operand.for_each_literal(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Operand> {
+        match self {
+            Operand::Column(_) | Operand::Literal(_) | Operand::Null | Operand::Subquery(_) => {
+                Vec::new()
+            }
+            Operand::Negate(o) => vec![o],
+            Operand::Arithmetic { left, right, .. } => vec![left, right],
+            Operand::Function { args, .. } => args.iter().collect(),
+            Operand::Case(case) => {
+                let mut children = case.subject.iter().collect::<Vec<_>>();
+                for (when, then) in &case.branches {
+                    if let CaseWhen::Value(v) = when {
+                        children.push(v);
+                    }
+                    children.push(then);
+                }
+                children.extend(&case.otherwise);
+                children
+            }
+            Operand::Window(w) => w
+                .args
+                .iter()
+                .chain(&w.partition_by)
+                .chain(w.order_by.iter().map(|o| &o.operand))
+                .collect(),
+        }
+    }
+
+    fn conditions(&self) -> Vec<&Expr> {
+        match self {
+            Operand::Case(case) => case
+                .branches
+                .iter()
+                .filter_map(|(when, _)| match when {
+                    CaseWhen::Condition(e) => Some(e),
+                    CaseWhen::Value(_) => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn aggregates(&self, result: &mut Vec<Aggregate>) {
+        if let Operand::Column(Column::Aggregate(a)) = self
+            && !result.contains(a)
+        {
+            result.push(a.clone());
+        }
+        self.children().iter().for_each(|o| o.aggregates(result));
+        self.conditions().iter().for_each(|e| e.aggregates(result));
+    }
+
+    pub fn windows(&self, result: &mut Vec<Window>) {
+        if let Operand::Window(w) = self
+            && !result.contains(w)
+        {
+            result.push(w.as_ref().clone());
+        }
+        self.children().iter().for_each(|o| o.windows(result));
+        self.conditions().iter().for_each(|e| e.windows(result));
+    }
+
+    pub fn subqueries<'a>(&'a self, result: &mut Vec<&'a Subquery>) {
+        if let Operand::Subquery(s) = self {
+            result.push(s);
+        }
+        self.children().iter().for_each(|o| o.subqueries(result));
+        self.conditions().iter().for_each(|e| e.subqueries(result));
+    }
+
+    // Plain field references, not counting the fields aggregates are computed over.
+    pub fn fields(&self, result: &mut Vec<String>) {
+        if let Operand::Column(Column::Field(name)) = self {
+            result.push(name.to_string());
+        }
+        self.children().iter().for_each(|o| o.fields(result));
+        self.conditions().iter().for_each(|e| e.fields(result));
+    }
+}
+
+impl Projection {
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<Projection, CustomError> {
+        match self {
+            Projection::Wildcard(table) => Ok(Projection::Wildcard(table.clone())),
+            Projection::Operand { operand, alias } => Ok(Projection::Operand {
+                operand: operand.map_fields(f)?,
+                alias: alias.clone(),
+            }),
+        }
+    }
+
+    pub fn operand(&self) -> Option<&Operand> {
+        match self {
+            Projection::Wildcard(_) => None,
+            Projection::Operand { operand, .. } => Some(operand),
+        }
+    }
+}
+
+impl Expr {
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<Expr, CustomError> {
+        let expr = match self {
+            Expr::Comparison(c) => Expr::Comparison(Comparison {
+                left: c.left.map_fields(f)?,
+                op: c.op.clone(),
+                // A right-hand name that is no column is left for binding, which reads
+                // TRUE and FALSE as values and reports anything else.
+                values: c
+                    .values
+                    .iter()
+                    .map(|v| match (v, v.map_fields(f)) {
+                        (
+                            Operand::Column(Column::Field(name)),
+                            Err(CustomError::UnknownField(_)),
+                        ) if !name.contains('.') => Ok(v.clone()),
+                        (_, mapped) => mapped,
+                    })
+                    .collect::<Result<_, _>>()?,
+            }),
+            Expr::And(l, r) => Expr::And(Box::new(l.map_fields(f)?), Box::new(r.map_fields(f)?)),
+            Expr::Or(l, r) => Expr::Or(Box::new(l.map_fields(f)?), Box::new(r.map_fields(f)?)),
+            Expr::Not(e) => Expr::Not(Box::new(e.map_fields(f)?)),
+            Expr::Exists(s) => Expr::Exists(s.clone()),
+        };
+        Ok(expr)
+    }
+
+    pub fn for_each_subquery(
+        &mut self,
+        f: &mut impl FnMut(&mut Subquery) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.for_each_subquery(f)?;
+                c.values.iter_mut().try_for_each(|v| v.for_each_subquery(f))
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.for_each_subquery(f)?;
+                r.for_each_subquery(f)
+            }
+            Expr::Not(e) => e.for_each_subquery(f),
+            Expr::Exists(s) => f(s),
+        }
+    }
+
+    pub fn for_each_literal(
+        &mut self,
+        f: &mut impl FnMut(&mut Literal) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.for_each_literal(f)?;
+                c.values.iter_mut().try_for_each(|v| v.for_each_literal(f))
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.for_each_literal(f)?;
+                r.for_each_literal(f)
+            }
+            Expr::Not(e) => e.for_each_literal(f),
+            Expr::Exists(s) => s.query.for_each_literal(f),
+        }
+    }
+
+    pub fn aggregates(&self, result: &mut Vec<Aggregate>) {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.aggregates(result);
+                c.values.iter().for_each(|v| v.aggregates(result));
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.aggregates(result);
+                r.aggregates(result);
+            }
+            Expr::Not(e) => e.aggregates(result),
+            Expr::Exists(_) => {}
+        }
+    }
+
+    pub fn windows(&self, result: &mut Vec<Window>) {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.windows(result);
+                c.values.iter().for_each(|v| v.windows(result));
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.windows(result);
+                r.windows(result);
+            }
+            Expr::Not(e) => e.windows(result),
+            Expr::Exists(_) => {}
+        }
+    }
+
+    pub fn subqueries<'a>(&'a self, result: &mut Vec<&'a Subquery>) {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.subqueries(result);
+                c.values.iter().for_each(|v| v.subqueries(result));
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.subqueries(result);
+                r.subqueries(result);
+            }
+            Expr::Not(e) => e.subqueries(result),
+            Expr::Exists(s) => result.push(s),
+        }
+    }
+
+    pub fn fields(&self, result: &mut Vec<String>) {
+        match self {
+            Expr::Comparison(c) => {
+                c.left.fields(result);
+                c.values.iter().for_each(|v| v.fields(result));
+            }
+            Expr::And(l, r) | Expr::Or(l, r) => {
+                l.fields(result);
+                r.fields(result);
+            }
+            Expr::Not(e) => e.fields(result),
+            Expr::Exists(_) => {}
+        }
+    }
+}
+
+impl SelectStatement {
+    // Rewrites every column reference, e.g. to resolve `title` into `library.title`.
+    pub fn map_fields(
+        &self,
+        f: &impl Fn(&str) -> Result<String, CustomError>,
+    ) -> Result<SelectStatement, CustomError> {
+        let map_expr = |expr: &Option<Expr>| expr.as_ref().map(|e| e.map_fields(f)).transpose();
+        Ok(SelectStatement {
+            distinct: self.distinct,
+            table_name: self.table_name.to_string(),
+            derived: self.derived.clone(),
+            joins: self.joins.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|p| p.map_fields(f))
+                .collect::<Result<_, _>>()?,
+            condition: map_expr(&self.condition)?,
+            group_by: self
+                .group_by
+                .iter()
+                .map(|g| f(g))
+                .collect::<Result<_, _>>()?,
+            having: map_expr(&self.having)?,
+            order_by: self
+                .order_by
+                .iter()
+                .map(|o| {
+                    Ok(OrderBy {
+                        operand: o.operand.map_fields(f)?,
+                        descending: o.descending,
+                    })
+                })
+                .collect::<Result<_, CustomError>>()?,
+            limit: self.limit,
+            offset: self.offset,
+        })
+    }
+
+    pub fn for_each_subquery(
+        &mut self,
+        f: &mut impl FnMut(&mut Subquery) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        for projection in self.fields.iter_mut() {
+            if let Projection::Operand { operand, .. } = projection {
+                operand.for_each_subquery(f)?;
+            }
+        }
+        for expr in self.condition.iter_mut().chain(self.having.iter_mut()) {
+            expr.for_each_subquery(f)?;
+        }
+        self.order_by
+            .iter_mut()
+            .try_for_each(|o| o.operand.for_each_subquery(f))
+    }
+
+    // Unlike `for_each_subquery`, this also visits the queries subqueries are made of.
+    pub fn for_each_literal(
+        &mut self,
+        f: &mut impl FnMut(&mut Literal) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        if let Some(derived) = &mut self.derived {
+            derived.for_each_literal(f)?;
+        }
+        for projection in self.fields.iter_mut() {
+            if let Projection::Operand { operand, .. } = projection {
+                operand.for_each_literal(f)?;
+            }
+        }
+        for expr in self.condition.iter_mut().chain(self.having.iter_mut()) {
+            expr.for_each_literal(f)?;
+        }
+        self.order_by
+            .iter_mut()
+            .try_for_each(|o| o.operand.for_each_literal(f))
+    }
+
+    // Replaces `*` and `table.*` with the columns returned by `expand`, in its order.
+    pub fn expand_wildcards(
+        &self,
+        expand: &impl Fn(Option<&str>) -> Result<Vec<String>, CustomError>,
+    ) -> Result<SelectStatement, CustomError> {
+        let mut fields = Vec::new();
+        for projection in &self.fields {
+            match projection {
+                Projection::Wildcard(table) => {
+                    fields.extend(expand(table.as_deref())?.into_iter().map(|name| {
+                        Projection::Operand {
+                            operand: Operand::Column(Column::Field(name)),
+                            alias: None,
+                        }
+                    }))
+                }
+                _ => fields.push(projection.clone()),
+            }
+        }
+        Ok(SelectStatement {
+            fields,
+            ..self.clone()
+        })
+    }
+
+    // Pairs of (operand, output name) in projection order.
+    pub fn output_columns(&self) -> Vec<(&Operand, String)> {
+        self.fields
+            .iter()
+            .filter_map(|p| match p {
+                Projection::Wildcard(_) => None,
+                Projection::Operand { operand, alias } => {
+                    Some((operand, alias.clone().unwrap_or_else(|| operand.name())))
+                }
+            })
+            .collect()
+    }
+
+    // Every aggregate the query has to compute, including those only used in HAVING or ORDER BY.
+    pub fn aggregates(&self) -> Vec<Aggregate> {
+        let mut result = Vec::new();
+        let operands = self
+            .fields
+            .iter()
+            .filter_map(Projection::operand)
+            .chain(self.order_by.iter().map(|o| &o.operand));
+        for operand in operands {
+            operand.aggregates(&mut result);
+        }
+        if let Some(having) = &self.having {
+            having.aggregates(&mut result);
+        }
+        result
+    }
+
+    // Window functions of the projection and ORDER BY, each once.
+    pub fn windows(&self) -> Vec<Window> {
+        let mut result = Vec::new();
+        let operands = self
+            .fields
+            .iter()
+            .filter_map(Projection::operand)
+            .chain(self.order_by.iter().map(|o| &o.operand));
+        for operand in operands {
+            operand.windows(&mut result);
+        }
+        result
+    }
+
+    // The subqueries `for_each_subquery` visits, without the queries they are made of.
+    pub fn subqueries(&self) -> Vec<&Subquery> {
+        let mut result = Vec::new();
+        let operands = self
+            .fields
+            .iter()
+            .filter_map(Projection::operand)
+            .chain(self.order_by.iter().map(|o| &o.operand));
+        for operand in operands {
+            operand.subqueries(&mut result);
+        }
+        for expr in self.condition.iter().chain(&self.having) {
+            expr.subqueries(&mut result);
+        }
+        result
+    }
+
+    // HAVING without GROUP BY makes the whole result one group.
+    pub fn is_grouped(&self) -> bool {
+        !self.group_by.is_empty() || !self.aggregates().is_empty() || self.having.is_some()
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}({})", self.function.as_str(), field),
+            None => write!(f, "{}(*)", self.function.as_str()),
+        }
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Column::Field(name) => write!(f, "{}", name),
+            Column::Aggregate(a) => write!(f, "{}", a),
+        }
+    }
+}
+
+impl Display for Projection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Projection::Wildcard(Some(table)) => write!(f, "{}.*", table),
+            Projection::Wildcard(None) => write!(f, "*"),
+            Projection::Operand {
+                operand,
+                alias: Some(alias),
+            } => write!(f, "{} AS {}", operand, alias),
+            Projection::Operand { operand, .. } => write!(f, "{}", operand),
+        }
+    }
+}
+
+impl Operand {
+    fn fmt_child(&self, f: &mut Formatter<'_>, parenthesize: bool) -> fmt::Result {
+        if parenthesize {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Operand::Arithmetic { op, .. } => op.precedence(),
+            _ => u8::MAX,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Column(c) => write!(f, "{}", c),
+            Operand::Literal(l) => write!(f, "{}", l),
+            Operand::Null => write!(f, "NULL"),
+            Operand::Negate(o) => {
+                write!(f, "-")?;
+                o.fmt_child(f, o.precedence() != u8::MAX)
+            }
+            Operand::Arithmetic { op, left, right } => {
+                left.fmt_child(f, left.precedence() < op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                right.fmt_child(f, right.precedence() <= op.precedence())
+            }
+            Operand::Function { function, args } => write!(
+                f,
+                "{}({})",
+                function.as_str(),
+                args.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Operand::Case(case) => {
+                write!(f, "CASE")?;
+                if let Some(subject) = &case.subject {
+                    write!(f, " {}", subject)?;
+                }
+                for (when, then) in &case.branches {
+                    match when {
+                        CaseWhen::Value(v) => write!(f, " WHEN {} THEN {}", v, then)?,
+                        CaseWhen::Condition(e) => write!(f, " WHEN {} THEN {}", e, then)?,
+                    }
+                }
+                if let Some(otherwise) = &case.otherwise {
+                    write!(f, " ELSE {}", otherwise)?;
+                }
+                write!(f, " END")
+            }
+            Operand::Subquery(s) => write!(f, "({})", s.query),
+            Operand::Window(w) => write!(f, "{}", w),
+        }
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fn list<T: Display>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match &self.function {
+            WindowFunction::Aggregate(a) => write!(f, "{} OVER (", a)?,
+            function => write!(f, "{}({}) OVER (", function.as_str(), list(&self.args))?,
+        }
+        let mut clauses = Vec::new();
+        if !self.partition_by.is_empty() {
+            clauses.push(format!("PARTITION BY {}", list(&self.partition_by)));
+        }
+        if !self.order_by.is_empty() {
+            clauses.push(format!("ORDER BY {}", list(&self.order_by)));
+        }
+        write!(f, "{})", clauses.join(" "))
+    }
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.field, self.value)
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let values = self
+            .values
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        match self.op {
+            Op::IsNull | Op::IsNotNull => write!(f, "{} {}", self.left, self.op),
+            Op::In => match self.values.as_slice() {
+                [Operand::Subquery(s)] => write!(f, "{} IN ({})", self.left, s.query),
+                _ => write!(f, "{} IN ({})", self.left, values.join(", ")),
+            },
+            Op::Between => write!(f, "{} BETWEEN {}", self.left, values.join(" AND ")),
+            _ => write!(f, "{} {} {}", self.left, self.op, values.join(", ")),
+        }
+    }
+}
+
+impl Display for AlterAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AlterAction::AddColumn {
+                name,
+                typ,
+                default: Some(default),
+            } => write!(f, "ADD COLUMN {} {} DEFAULT {}", name, typ, default),
+            AlterAction::AddColumn { name, typ, .. } => write!(f, "ADD COLUMN {} {}", name, typ),
+            AlterAction::DropColumn(name) => write!(f, "DROP COLUMN {}", name),
+            AlterAction::RenameColumn { from, to } => write!(f, "RENAME COLUMN {} TO {}", from, to),
+            AlterAction::RenameTo(name) => write!(f, "RENAME TO {}", name),
+        }
+    }
+}
+
+impl Expr {
+    fn fmt_operand(&self, f: &mut Formatter<'_>, parent_binds_tighter: bool) -> fmt::Result {
+        match self {
+            Expr::Or(..) | Expr::And(..) if parent_binds_tighter => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Comparison(c) => write!(f, "{}", c),
+            Expr::Or(l, r) => {
+                l.fmt_operand(f, false)?;
+                write!(f, " OR ")?;
+                r.fmt_operand(f, false)
+            }
+            Expr::And(l, r) => {
+                l.fmt_operand(f, matches!(**l, Expr::Or(..)))?;
+                write!(f, " AND ")?;
+                r.fmt_operand(f, matches!(**r, Expr::Or(..)))
+            }
+            Expr::Not(e) => {
+                write!(f, "NOT ")?;
+                e.fmt_operand(f, true)
+            }
+            Expr::Exists(s) => write!(f, "EXISTS ({})", s.query),
+        }
+    }
+}
+
+impl Display for OrderBy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.descending {
+            write!(f, "{} DESC", self.operand)
+        } else {
+            write!(f, "{} ASC", self.operand)
+        }
+    }
+}
+
+impl Display for SelectStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SELECT {}{} FROM ",
+            if self.distinct { "DISTINCT " } else { "" },
+            self.fields
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        )?;
+        match &self.derived {
+            Some(query) => write!(f, "({}) AS {}", query, self.table_name)?,
+            None => write!(f, "{}", self.table_name)?,
+        }
+        for join in &self.joins {
+            write!(f, " {}", join)?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " WHERE {}", condition)?;
+        }
+        if !self.group_by.is_empty() {
+            write!(f, " GROUP BY {}", self.group_by.join(", "))?;
+        }
+        if let Some(having) = &self.having {
+            write!(f, " HAVING {}", having)?;
+        }
+        fmt_order_and_limit(f, &self.order_by, self.limit, self.offset)
+    }
+}
+
+fn fmt_order_and_limit(
+    f: &mut Formatter<'_>,
+    order_by: &[OrderBy],
+    limit: Option<usize>,
+    offset: usize,
+) -> fmt::Result {
+    if !order_by.is_empty() {
+        write!(
+            f,
+            " ORDER BY {}",
+            order_by
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+    }
+    if let Some(limit) = limit {
+        write!(f, " LIMIT {}", limit)?;
+        if offset > 0 {
+            write!(f, " OFFSET {}", offset)?;
+        }
+    }
+    Ok(())
+}
+
+impl SetOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SetOperator::Union => "UNION",
+            SetOperator::Intersect => "INTERSECT",
+            SetOperator::Except => "EXCEPT",
+        }
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::Select(select) => write!(f, "{}", select),
+            Query::Compound(compound) => {
+                write!(
+                    f,
+                    "{} {}{} {}",
+                    compound.left,
+                    compound.operator.as_str(),
+                    if compound.all { " ALL" } else { "" },
+                    compound.right
+                )?;
+                fmt_order_and_limit(f, &compound.order_by, compound.limit, compound.offset)
+            }
+            Query::With(with) => {
+                write!(f, "WITH ")?;
+                if with.recursive {
+                    write!(f, "RECURSIVE ")?;
+                }
+                let ctes = with
+                    .ctes
+                    .iter()
+                    .map(|cte| match cte.columns.is_empty() {
+                        true => format!("{} AS ({})", cte.name, cte.query),
+                        false => format!(
+                            "{}({}) AS ({})",
+                            cte.name,
+                            cte.columns.join(", "),
+                            cte.query
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{} {}", ctes.join(", "), with.body)
+            }
+        }
+    }
+}
+
+impl Query {
+    pub fn for_each_literal(
+        &mut self,
+        f: &mut impl FnMut(&mut Literal) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Query::Select(select) => select.for_each_literal(f),
+            Query::Compound(compound) => {
+                compound.left.for_each_literal(f)?;
+                compound.right.for_each_literal(f)?;
+                compound
+                    .order_by
+                    .iter_mut()
+                    .try_for_each(|o| o.operand.for_each_literal(f))
+            }
+            Query::With(with) => {
+                for cte in with.ctes.iter_mut() {
+                    cte.query.for_each_literal(f)?;
+                }
+                with.body.for_each_literal(f)
+            }
+        }
+    }
+
+    // Whether a FROM or JOIN anywhere in the query, subqueries included, reads `name`.
+    pub fn references(&self, name: &str) -> bool {
+        match self {
+            Query::Select(select) => {
+                (match &select.derived {
+                    Some(query) => query.references(name),
+                    None => select.table_name == name,
+                }) || select.joins.iter().any(|j| j.table_name == name)
+                    || select.subqueries().iter().any(|s| s.query.references(name))
+            }
+            Query::Compound(compound) => {
+                compound.left.references(name) || compound.right.references(name)
+            }
+            Query::With(with) => {
+                with.ctes.iter().any(|c| c.query.references(name))
+                    || (!with.ctes.iter().any(|c| c.name == name) && with.body.references(name))
+            }
+        }
+    }
+}
+
+impl Display for Join {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            JoinKind::Inner => "INNER JOIN",
+            JoinKind::Left => "LEFT JOIN",
+        };
+        write!(
+            f,
+            "{} {} ON {} = {}",
+            kind, self.table_name, self.left, self.right
+        )
+    }
+}
+
+impl Statement {
+    // The literals a prepared statement may hold parameters in. The statement inside
+    // PREPARE keeps its own, so it is not visited.
+    pub fn for_each_literal(
+        &mut self,
+        f: &mut impl FnMut(&mut Literal) -> Result<(), CustomError>,
+    ) -> Result<(), CustomError> {
+        match self {
+            Statement::Select(query) | Statement::Explain { query, .. } => {
+                query.for_each_literal(f)
+            }
+            Statement::Insert(insert) => {
+                match &mut insert.rows {
+                    InsertRows::Values(rows) => {
+                        for literal in rows.iter_mut().flatten().flatten() {
+                            f(literal)?;
+                        }
+                    }
+                    InsertRows::Query(query) => query.for_each_literal(f)?,
+                }
+                if let OnConflict::Update(assignments) = &mut insert.on_conflict {
+                    for (_, operand) in assignments.iter_mut() {
+                        operand.for_each_literal(f)?;
+                    }
+                }
+                Ok(())
+            }
+            Statement::Update(update) => {
+                for assignment in update.assignments.iter_mut() {
+                    f(&mut assignment.value)?;
+                }
+                update
+                    .condition
+                    .iter_mut()
+                    .try_for_each(|c| c.for_each_literal(f))
+            }
+            Statement::Delete(delete) => {
+                delete.key.iter_mut().try_for_each(&mut *f)?;
+                delete
+                    .condition
+                    .iter_mut()
+                    .try_for_each(|c| c.for_each_literal(f))
+            }
+            Statement::Alter(AlterStatement {
+                action:
+                    AlterAction::AddColumn {
+                        default: Some(default),
+                        ..
+                    },
+                ..
+            }) => f(default),
+            Statement::Execute { arguments, .. } => arguments.iter_mut().try_for_each(f),
+            _ => Ok(()),
+        }
+    }
+}
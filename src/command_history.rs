@@ -15,3 +15,9 @@ impl CommandHistory {
         &self.entries
     }
 }
+
+impl Default for CommandHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
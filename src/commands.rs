@@ -2,12 +2,20 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, Write};
 
+use crate::ast::{
+    AlterAction, AlterStatement, Assignment, Column, CreateStatement, DeleteStatement,
+    DropStatement, Expr, InsertRows, InsertStatement, Literal, OnConflict, Operand, Query,
+    TruncateStatement, UpdateStatement,
+};
 use crate::command_history::CommandHistory;
 use crate::custom_error::CustomError;
 use crate::database::DatabaseKey;
-use crate::database::{self, Table};
+use crate::database::{self, Record, Table, Value};
+use crate::explain::explain;
+use crate::expression::{Scalar, bind_typed, canonical_type};
 use crate::handlers::handle_input;
-use crate::parsers::{self, parse_fields_list};
+use crate::parsers;
+use crate::query::{ResultSet, execute_query, run_subquery};
 use database::Database;
 use parsers::parse_fields;
 
@@ -22,21 +30,51 @@ pub struct Create<'a, K: DatabaseKey> {
     database: &'a mut Database<K>,
     name: String,
     key_field: String,
-    fields: HashMap<String, String>,
+    fields: Vec<(String, String)>,
 }
 
 pub struct Insert<'a, K: DatabaseKey> {
-    table: &'a mut Table<K>,
-    record_string: String,
+    database: &'a mut Database<K>,
+    table_name: String,
+    columns: Vec<String>,
+    rows: InsertRows,
+    conflict_target: Option<String>,
+    on_conflict: OnConflict,
 }
 pub struct Delete<'a, K: DatabaseKey> {
     table: &'a mut Table<K>,
-    key_string: String,
+    key: Option<Literal>,
+    condition: Option<Expr>,
 }
 pub struct Select<'a, K: DatabaseKey> {
+    database: &'a mut Database<K>,
+    query: Query,
+}
+pub struct Explain<'a, K: DatabaseKey> {
+    database: &'a mut Database<K>,
+    query: Query,
+    analyze: bool,
+}
+pub struct Update<'a, K: DatabaseKey> {
+    table: &'a mut Table<K>,
+    assignments: Vec<Assignment>,
+    condition: Option<Expr>,
+}
+
+pub struct Drop<'a, K: DatabaseKey> {
+    database: &'a mut Database<K>,
+    name: String,
+    if_exists: bool,
+}
+
+pub struct Truncate<'a, K: DatabaseKey> {
     table: &'a mut Table<K>,
-    fields: Vec<String>,
-    cond_string: Option<String>,
+}
+
+pub struct Alter<'a, K: DatabaseKey> {
+    database: &'a mut Database<K>,
+    table_name: String,
+    action: AlterAction,
 }
 
 pub struct SaveAs<'a> {
@@ -51,150 +89,337 @@ pub struct ReadFrom<'a, K: DatabaseKey> {
 }
 
 impl<'a, K: DatabaseKey> Create<'a, K> {
-    pub fn new(database: &'a mut Database<K>, input: &str) -> Result<Self, CustomError> {
-        let key_pos = input
-            .find("KEY")
-            .ok_or_else(|| CustomError::MissingKeyword("KEY".to_string()))?;
-        let table_name = input["CREATE".len()..key_pos].trim();
-        if table_name.is_empty() {
-            return Err(CustomError::MissingField("Table name".to_string()));
-        }
-
-        let after_key = &input[key_pos + "KEY".len()..];
-
-        let fields_pos = after_key
-            .find("FIELDS")
-            .ok_or_else(|| CustomError::MissingKeyword("FIELDS".to_string()))?;
-        let key_field = after_key[..fields_pos].trim();
-        if key_field.is_empty() {
-            return Err(CustomError::MissingField("Key field".to_string()));
-        }
-
-        let fields_str = after_key[fields_pos + "FIELDS".len()..].trim();
-        let fields = parse_fields(fields_str)?;
+    pub fn new(
+        database: &'a mut Database<K>,
+        statement: CreateStatement,
+    ) -> Result<Self, CustomError> {
+        parse_fields(&statement.fields)?;
 
         Ok(Self {
             database,
-            name: table_name.into(),
-            key_field: key_field.into(),
-            fields,
+            name: statement.table_name,
+            key_field: statement.key_field,
+            fields: statement.fields,
         })
     }
 }
 
 impl<'a, K: DatabaseKey> Insert<'a, K> {
-    pub fn new(database: &'a mut Database<K>, input: &str) -> Result<Self, CustomError> {
-        let into_pos = input
-            .find("INTO")
-            .ok_or_else(|| CustomError::MissingKeyword("INTO".to_string()))?;
-
-        let record_string = input["INSERT".len()..into_pos].trim().to_string();
-        if record_string.is_empty() {
-            return Err(CustomError::MissingField("Record data".to_string()));
+    pub fn new(
+        database: &'a mut Database<K>,
+        mut statement: InsertStatement,
+    ) -> Result<Self, CustomError> {
+        let table = database.get_table(&statement.table_name)?;
+        if let Some(target) = &statement.conflict_target
+            && *target != table.key_field
+        {
+            return Err(CustomError::ConflictError(format!(
+                "{} is not the key of {}, {} is",
+                target, table.table_name, table.key_field
+            )));
         }
-
-        let after_into = &input[into_pos + "INTO".len()..];
-        let table_name = after_into.trim();
-        if table_name.is_empty() {
-            return Err(CustomError::MissingField("Table name".to_string()));
+        if let OnConflict::Update(assignments) = &mut statement.on_conflict {
+            for (_, operand) in assignments {
+                operand.for_each_subquery(&mut |subquery| run_subquery(database, subquery))?;
+            }
         }
 
-        let table = database.get_table_mut(table_name)?;
-
         Ok(Self {
-            table,
-            record_string,
+            database,
+            table_name: statement.table_name,
+            columns: statement.columns,
+            rows: statement.rows,
+            conflict_target: statement.conflict_target,
+            on_conflict: statement.on_conflict,
         })
     }
-}
 
-impl<'a, K: DatabaseKey> Delete<'a, K> {
-    pub fn new(database: &'a mut Database<K>, input: &str) -> Result<Self, CustomError> {
-        input
-            .find("DELETE")
-            .ok_or_else(|| CustomError::MissingKeyword("DELETE".to_string()))?;
+    // The named columns, checked against the table, or all of them in table order.
+    fn target_columns(&self, table: &Table<K>) -> Result<Vec<String>, CustomError> {
+        if self.columns.is_empty() {
+            return Ok(table.columns.clone());
+        }
+        let mut seen = Vec::new();
+        for column in &self.columns {
+            if !table.fields.contains_key(column) {
+                return Err(CustomError::UnknownField(column.to_string()));
+            }
+            if seen.contains(&column) {
+                return Err(CustomError::FieldParseError(column.to_string()));
+            }
+            seen.push(column);
+        }
+        Ok(self.columns.clone())
+    }
 
-        let from_pos = input
-            .find("FROM")
-            .ok_or_else(|| CustomError::MissingKeyword("FROM".to_string()))?;
+    // Builds every record before any is added, so a bad row leaves the table as it was.
+    fn records(&mut self) -> Result<Vec<Record>, CustomError> {
+        let result = match &self.rows {
+            InsertRows::Values(rows) => {
+                let table = self.database.get_table(&self.table_name)?;
+                let columns = self.target_columns(table)?;
+                return rows
+                    .iter()
+                    .map(|row| values_record(table, &columns, row))
+                    .collect();
+            }
+            InsertRows::Query(query) => execute_query(self.database, query)?,
+        };
+        let table = self.database.get_table(&self.table_name)?;
+        let columns = self.target_columns(table)?;
+        selected_records(table, &columns, &result)
+    }
+}
 
-        let table_name = &input["FROM".len() + from_pos..].trim().to_string();
-        let key_string = input["DELETE".len()..from_pos].trim().to_string();
-        let table = database.get_table_mut(table_name)?;
+fn values_record<K: DatabaseKey>(
+    table: &Table<K>,
+    columns: &[String],
+    row: &[Option<Literal>],
+) -> Result<Record, CustomError> {
+    if row.len() != columns.len() {
+        return Err(CustomError::ValueParseError(format!(
+            "{} values given for {} columns",
+            row.len(),
+            columns.len()
+        )));
+    }
+    let assignments = columns
+        .iter()
+        .zip(row)
+        .filter_map(|(field, value)| {
+            value.clone().map(|value| Assignment {
+                field: field.to_string(),
+                value,
+            })
+        })
+        .collect::<Vec<_>>();
+    table.parse_record(&assignments)
+}
 
-        Ok(Self { table, key_string })
+// SELECT output goes to the columns by position; an Int may fill a Float column.
+fn selected_records<K: DatabaseKey>(
+    table: &Table<K>,
+    columns: &[String],
+    result: &ResultSet,
+) -> Result<Vec<Record>, CustomError> {
+    if result.columns.len() != columns.len() {
+        return Err(CustomError::ValueParseError(format!(
+            "SELECT returns {} columns for {} columns",
+            result.columns.len(),
+            columns.len()
+        )));
+    }
+    let mut targets = Vec::new();
+    for ((column, source), typ) in columns.iter().zip(&result.columns).zip(&result.types) {
+        let field_type = canonical_type(&table.fields[column]);
+        if *typ != field_type && typ != "Null" && !(typ == "Int" && field_type == "Float") {
+            return Err(CustomError::TypeError(format!(
+                "cannot insert {} ({}) into {} ({})",
+                source, typ, column, field_type
+            )));
+        }
+        targets.push((column, source, field_type));
     }
+    // The values are checked too, in case one does not match its column's type.
+    result
+        .records
+        .iter()
+        .map(|r| {
+            let mut values = HashMap::new();
+            for (column, source, field_type) in &targets {
+                let value = match r.values.get(*source) {
+                    None => continue,
+                    Some(Value::Int(i)) if field_type == "Float" => Value::Float(*i as f64),
+                    Some(value) if value.type_name() == field_type => value.clone(),
+                    Some(value) => {
+                        return Err(CustomError::TypeError(format!(
+                            "cannot insert {} ({}) into {} ({})",
+                            value.as_string(),
+                            value.type_name(),
+                            column,
+                            field_type
+                        )));
+                    }
+                };
+                values.insert(column.to_string(), value);
+            }
+            Ok(Record::with_values(values))
+        })
+        .collect()
 }
-impl<'a, K: DatabaseKey> Select<'a, K> {
-    pub fn new(database: &'a mut Database<K>, input: &str) -> Result<Self, CustomError> {
-        input
-            .find("SELECT")
-            .ok_or_else(|| CustomError::MissingKeyword("SELECT".to_string()))?;
 
-        let from_pos = input
-            .find("FROM")
-            .ok_or_else(|| CustomError::MissingKeyword("FROM".to_string()))?;
+// The SET operands of ON CONFLICT DO UPDATE see the stored record's fields, and those
+// of the row that collided with it as `excluded.field`.
+fn bind_conflict_update<K: DatabaseKey>(
+    table: &Table<K>,
+    assignments: &[(String, Operand)],
+) -> Result<Vec<(String, Scalar)>, CustomError> {
+    let mut fields = table.fields.clone();
+    for (name, typ) in &table.fields {
+        fields.insert(format!("excluded.{}", name), typ.to_string());
+    }
+    let mut bound = Vec::new();
+    for (field, operand) in assignments {
+        if *field == table.key_field {
+            return Err(CustomError::KeyFieldModification(field.to_string()));
+        }
+        let field_type = table
+            .fields
+            .get(field)
+            .map(|t| canonical_type(t))
+            .ok_or_else(|| CustomError::UnknownField(field.to_string()))?;
+        // As on the right of a WHERE comparison, only TRUE and FALSE are bare values.
+        let operand = match operand {
+            Operand::Column(Column::Field(name))
+                if name.eq_ignore_ascii_case("true") || name.eq_ignore_ascii_case("false") =>
+            {
+                Operand::Literal(Literal::Word(name.to_string()))
+            }
+            _ => operand.map_fields(&|name| {
+                Ok(match name.split_once('.') {
+                    Some((prefix, name)) if prefix.eq_ignore_ascii_case("excluded") => {
+                        format!("excluded.{}", name)
+                    }
+                    _ => name.to_string(),
+                })
+            })?,
+        };
+        let (scalar, typ) = bind_typed(&operand, &fields, Some(&field_type))?;
+        let scalar = match (typ.as_str(), field_type.as_str()) {
+            (a, b) if a == b => scalar,
+            ("Int", "Float") => Scalar::ToFloat(Box::new(scalar)),
+            _ => {
+                return Err(CustomError::TypeError(format!(
+                    "cannot set {} ({}) to {} ({})",
+                    field, field_type, operand, typ
+                )));
+            }
+        };
+        bound.push((field.to_string(), scalar));
+    }
+    Ok(bound)
+}
 
-        let where_pos = input.find("WHERE");
+// Every operand is evaluated against the record as it was, so `SET a = b, b = a` swaps.
+fn conflict_update(existing: &Record, excluded: Record, set: &[(String, Scalar)]) -> Record {
+    let mut row = existing.clone();
+    for (name, value) in excluded.values {
+        row.values.insert(format!("excluded.{}", name), value);
+    }
+    let mut updated = existing.clone();
+    for (field, scalar) in set {
+        match scalar.evaluate(&row) {
+            Some(value) => updated.values.insert(field.to_string(), value),
+            None => updated.values.remove(field),
+        };
+    }
+    updated
+}
 
-        let fields_str = input["SELECT".len()..from_pos].trim();
-        let fields = parse_fields_list(fields_str);
+impl<'a, K: DatabaseKey> Delete<'a, K> {
+    pub fn new(
+        database: &'a mut Database<K>,
+        mut statement: DeleteStatement,
+    ) -> Result<Self, CustomError> {
+        if let Some(condition) = &mut statement.condition {
+            condition.for_each_subquery(&mut |subquery| run_subquery(database, subquery))?;
+        }
+        let table = database.get_table_mut(&statement.table_name)?;
 
-        let (table_name, cond_string) = if let Some(pos) = where_pos {
-            let name = input[from_pos + "FROM".len()..pos].trim();
-            let cond_string = input[pos + "WHERE".len()..].trim().to_string();
-            (name, Some(cond_string))
-        } else {
-            let name = input[from_pos + "FROM".len()..].trim();
-            (name, None)
-        };
-        let table = database.get_table_mut(table_name)?;
         Ok(Self {
             table,
-            fields,
-            cond_string,
+            key: statement.key,
+            condition: statement.condition,
+        })
+    }
+}
+impl<'a, K: DatabaseKey> Select<'a, K> {
+    pub fn new(database: &'a mut Database<K>, query: Query) -> Result<Self, CustomError> {
+        Ok(Self { database, query })
+    }
+}
+impl<'a, K: DatabaseKey> Explain<'a, K> {
+    pub fn new(database: &'a mut Database<K>, query: Query, analyze: bool) -> Self {
+        Self {
+            database,
+            query,
+            analyze,
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Update<'a, K> {
+    pub fn new(
+        database: &'a mut Database<K>,
+        mut statement: UpdateStatement,
+    ) -> Result<Self, CustomError> {
+        if let Some(condition) = &mut statement.condition {
+            condition.for_each_subquery(&mut |subquery| run_subquery(database, subquery))?;
+        }
+        let table = database.get_table_mut(&statement.table_name)?;
+        Ok(Self {
+            table,
+            assignments: statement.assignments,
+            condition: statement.condition,
+        })
+    }
+}
+impl<'a, K: DatabaseKey> Drop<'a, K> {
+    pub fn new(database: &'a mut Database<K>, statement: DropStatement) -> Self {
+        Self {
+            database,
+            name: statement.table_name,
+            if_exists: statement.if_exists,
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Truncate<'a, K> {
+    pub fn new(
+        database: &'a mut Database<K>,
+        statement: TruncateStatement,
+    ) -> Result<Self, CustomError> {
+        let table = database.get_table_mut(&statement.table_name)?;
+        Ok(Self { table })
+    }
+}
+impl<'a, K: DatabaseKey> Alter<'a, K> {
+    pub fn new(
+        database: &'a mut Database<K>,
+        statement: AlterStatement,
+    ) -> Result<Self, CustomError> {
+        database.get_table(&statement.table_name)?;
+        Ok(Self {
+            database,
+            table_name: statement.table_name,
+            action: statement.action,
         })
     }
 }
 impl<'a> SaveAs<'a> {
-    pub fn new(input: &str, history: &'a mut CommandHistory) -> Result<Self, CustomError> {
-        input
-            .find("SAVE_AS")
-            .ok_or_else(|| CustomError::MissingKeyword("SAVE_AS".to_string()))?;
-
-        let file_path = input["SAVE_AS".len()..].trim().to_string();
-
-        Ok(Self { file_path, history })
+    pub fn new(file_path: String, history: &'a mut CommandHistory) -> Self {
+        Self { file_path, history }
     }
 }
 
 impl<'a, K: DatabaseKey> ReadFrom<'a, K> {
     pub fn new(
-        input: &str,
+        file_path: String,
         history: &'a mut CommandHistory,
         database: &'a mut Database<K>,
-    ) -> Result<Self, CustomError> {
-        input
-            .find("READ_FROM")
-            .ok_or_else(|| CustomError::MissingKeyword("READ_FROM".to_string()))?;
-
-        let file_path = input["READ_FROM".len()..].trim().to_string();
-
-        Ok(Self {
+    ) -> Self {
+        Self {
             file_path,
             history,
             database,
-        })
+        }
     }
 }
 
 impl<'a, K: DatabaseKey> Command for Create<'a, K> {
     fn execute(&mut self) -> Result<String, CustomError> {
-        match self.database.create_table(
-            self.name.clone(),
-            self.key_field.clone(),
-            self.fields.clone(),
-        ) {
+        match self
+            .database
+            .create_table(self.name.clone(), self.key_field.clone(), &self.fields)
+        {
             Ok(_) => Ok("Table created succesfully".to_string()),
             Err(e) => Err(e),
         }
@@ -203,37 +428,119 @@ impl<'a, K: DatabaseKey> Command for Create<'a, K> {
 
 impl<'a, K: DatabaseKey> Command for Insert<'a, K> {
     fn execute(&mut self) -> Result<String, CustomError> {
-        let record = self.table.parse_record(&self.record_string)?;
-
-        match self.table.add_record(record) {
-            Ok(_) => Ok("Data inserted succesfully".to_string()),
+        let records = self.records()?;
+        let table = self.database.get_table_mut(&self.table_name)?;
+
+        let result = match &self.on_conflict {
+            OnConflict::Fail => table.add_records(records, |_, record| {
+                Err(CustomError::RecordAlreadyExists(record.to_string()))
+            }),
+            OnConflict::Nothing => table.add_records(records, |_, _| Ok(None)),
+            OnConflict::Replace => table.add_records(records, |_, record| Ok(Some(record))),
+            OnConflict::Update(assignments) => {
+                let set = bind_conflict_update(table, assignments)?;
+                table.add_records(records, |existing, record| {
+                    Ok(Some(conflict_update(existing, record, &set)))
+                })
+            }
+        };
+        match result {
+            Ok(count) => Ok(format!(
+                "Data inserted succesfully, {} records affected",
+                count
+            )),
             Err(e) => Err(e),
         }
     }
 }
 impl<'a, K: DatabaseKey> Command for Delete<'a, K> {
     fn execute(&mut self) -> Result<String, CustomError> {
-        match self.table.remove_record(&self.key_string) {
-            Ok(_) => Ok("Data deleted succesfully".to_string()),
+        let count = match &self.key {
+            Some(key) => self.table.remove_record(key.text()).map(|_| 1),
+            None => self.table.delete_records(&self.condition),
+        };
+        match count {
+            Ok(count) => Ok(format!(
+                "Data deleted succesfully, {} records affected",
+                count
+            )),
             Err(e) => Err(e),
         }
     }
 }
 impl<'a, K: DatabaseKey> Command for Select<'a, K> {
     fn execute(&mut self) -> Result<String, CustomError> {
-        match self.table.select_records(&self.fields, &self.cond_string) {
-            Ok(v) => {
-                let result = v
-                    .iter()
-                    .map(ToString::to_string)
-                    .collect::<Vec<_>>()
-                    .join(" | ");
-                Ok(result)
+        match execute_query(self.database, &self.query) {
+            Ok(v) => Ok(v.to_string()),
+            Err(e) => Err(e),
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Command for Explain<'a, K> {
+    fn execute(&mut self) -> Result<String, CustomError> {
+        Ok(explain(self.database, &self.query, self.analyze)?.to_string())
+    }
+}
+impl<'a, K: DatabaseKey> Command for Update<'a, K> {
+    fn execute(&mut self) -> Result<String, CustomError> {
+        match self
+            .table
+            .update_records(&self.assignments, &self.condition)
+        {
+            Ok(count) => Ok(format!(
+                "Data updated succesfully, {} records affected",
+                count
+            )),
+            Err(e) => Err(e),
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Command for Drop<'a, K> {
+    fn execute(&mut self) -> Result<String, CustomError> {
+        match self.database.drop_table(&self.name) {
+            Ok(_) => Ok("Table dropped succesfully".to_string()),
+            Err(CustomError::TableNotFound(_)) if self.if_exists => {
+                Ok(format!("Table {} does not exist, skipping", self.name))
             }
             Err(e) => Err(e),
         }
     }
 }
+impl<'a, K: DatabaseKey> Command for Truncate<'a, K> {
+    fn execute(&mut self) -> Result<String, CustomError> {
+        let count = self.table.truncate();
+        Ok(format!(
+            "Table truncated succesfully, {} records removed",
+            count
+        ))
+    }
+}
+impl<'a, K: DatabaseKey> Command for Alter<'a, K> {
+    fn execute(&mut self) -> Result<String, CustomError> {
+        let result = match &self.action {
+            AlterAction::RenameTo(new_name) => {
+                self.database.rename_table(&self.table_name, new_name)
+            }
+            AlterAction::AddColumn { name, typ, default } => self
+                .database
+                .get_table_mut(&self.table_name)?
+                .add_column(name, typ, default.as_ref()),
+            AlterAction::DropColumn(name) => self
+                .database
+                .get_table_mut(&self.table_name)?
+                .drop_column(name),
+            AlterAction::RenameColumn { from, to } => self
+                .database
+                .get_table_mut(&self.table_name)?
+                .rename_column(from, to),
+        };
+
+        match result {
+            Ok(_) => Ok("Table altered succesfully".to_string()),
+            Err(e) => Err(e),
+        }
+    }
+}
 
 impl<'a> Command for SaveAs<'a> {
     fn execute(&mut self) -> Result<String, CustomError> {
@@ -280,31 +587,404 @@ impl<'a, K: DatabaseKey> Serialize for Create<'a, K> {
 
 impl<'a, K: DatabaseKey> Serialize for Insert<'a, K> {
     fn serialize(&mut self) -> String {
-        format!(
-            "INSERT {} INTO {}",
-            self.record_string, self.table.table_name
-        )
+        let mut result = match self.on_conflict {
+            OnConflict::Replace => format!("REPLACE INTO {}", self.table_name),
+            _ => format!("INSERT INTO {}", self.table_name),
+        };
+        if !self.columns.is_empty() {
+            result.push_str(&format!(" ({})", self.columns.join(", ")));
+        }
+        match &self.rows {
+            InsertRows::Values(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        let values = row
+                            .iter()
+                            .map(|v| match v {
+                                Some(literal) => literal.to_string(),
+                                None => "NULL".to_string(),
+                            })
+                            .collect::<Vec<_>>();
+                        format!("({})", values.join(", "))
+                    })
+                    .collect::<Vec<_>>();
+                result.push_str(&format!(" VALUES {}", rows.join(", ")));
+            }
+            InsertRows::Query(query) => result.push_str(&format!(" {}", query)),
+        }
+        let target = match &self.conflict_target {
+            Some(target) => format!(" ({})", target),
+            None => String::new(),
+        };
+        match &self.on_conflict {
+            OnConflict::Fail | OnConflict::Replace => {}
+            OnConflict::Nothing => {
+                result.push_str(&format!(" ON CONFLICT{} DO NOTHING", target));
+            }
+            OnConflict::Update(assignments) => {
+                let assignments = assignments
+                    .iter()
+                    .map(|(field, operand)| format!("{} = {}", field, operand))
+                    .collect::<Vec<_>>();
+                result.push_str(&format!(
+                    " ON CONFLICT{} DO UPDATE SET {}",
+                    target,
+                    assignments.join(", ")
+                ));
+            }
+        }
+        result
     }
 }
 impl<'a, K: DatabaseKey> Serialize for Delete<'a, K> {
     fn serialize(&mut self) -> String {
-        format!("DELETE {} FROM {}", self.key_string, self.table.table_name)
+        match (&self.key, &self.condition) {
+            (Some(key), _) => format!("DELETE {} FROM {}", key, self.table.table_name),
+            (None, Some(condition)) => {
+                format!("DELETE FROM {} WHERE {}", self.table.table_name, condition)
+            }
+            (None, None) => format!("DELETE FROM {}", self.table.table_name),
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Serialize for Explain<'a, K> {
+    fn serialize(&mut self) -> String {
+        match self.analyze {
+            true => format!("EXPLAIN ANALYZE {}", self.query),
+            false => format!("EXPLAIN {}", self.query),
+        }
     }
 }
 impl<'a, K: DatabaseKey> Serialize for Select<'a, K> {
     fn serialize(&mut self) -> String {
-        match &self.cond_string {
-            Some(cond_string) => format!(
-                "SELECT {} FROM {} WHERE {}",
-                self.fields.join(", "),
-                self.table.table_name,
-                cond_string
+        self.query.to_string()
+    }
+}
+impl<'a, K: DatabaseKey> Serialize for Update<'a, K> {
+    fn serialize(&mut self) -> String {
+        let assignments = self
+            .assignments
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &self.condition {
+            Some(condition) => format!(
+                "UPDATE {} SET {} WHERE {}",
+                self.table.table_name, assignments, condition
             ),
-            None => format!(
-                "SELECT {} FROM {}",
-                self.fields.join(", "),
-                self.table.table_name,
+            None => format!("UPDATE {} SET {}", self.table.table_name, assignments),
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Serialize for Drop<'a, K> {
+    fn serialize(&mut self) -> String {
+        if self.if_exists {
+            format!("DROP TABLE IF EXISTS {}", self.name)
+        } else {
+            format!("DROP TABLE {}", self.name)
+        }
+    }
+}
+impl<'a, K: DatabaseKey> Serialize for Truncate<'a, K> {
+    fn serialize(&mut self) -> String {
+        format!("TRUNCATE TABLE {}", self.table.table_name)
+    }
+}
+impl<'a, K: DatabaseKey> Serialize for Alter<'a, K> {
+    fn serialize(&mut self) -> String {
+        format!("ALTER TABLE {} {}", self.table_name, self.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{
+        command_history::CommandHistory,
+        custom_error::CustomError,
+        database::Database,
+        test_util::{database, replay, run, run_logged},
+    };
+
+    #[test]
+    fn failed_multi_row_insert_changes_nothing() {
+        let mut database = database(&[]);
+        let mut history = CommandHistory::new();
+        for statement in [
+            "CREATE t KEY id FIELDS id: Int, n: Int",
+            "INSERT INTO t VALUES (1, 10)",
+        ] {
+            run_logged(&mut database, &mut history, statement).unwrap();
+        }
+        let failing = [
+            "INSERT INTO t VALUES (2, 20), (1, 30)",
+            "INSERT INTO t VALUES (2, 20), (3, 'x')",
+            "INSERT INTO t (n) VALUES (20)",
+            "INSERT INTO t SELECT id + 1, n FROM t UNION ALL SELECT id, n FROM t",
+        ];
+        for statement in failing {
+            let result = run_logged(&mut database, &mut history, statement);
+            assert!(result.is_err(), "{}", statement);
+        }
+        assert_eq!(run(&mut database, "SELECT * FROM t").unwrap(), "id=1, n=10");
+        assert_eq!(history.list().len(), 2);
+    }
+
+    #[test]
+    fn on_conflict_resolves_existing_keys() {
+        let mut database = database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int, s: String",
+            "INSERT INTO t VALUES (1, 10, 'a'), (2, 20, 'b')",
+            "INSERT INTO t VALUES (1, 99, 'x'), (3, 30, 'c') ON CONFLICT DO NOTHING",
+            "INSERT INTO t VALUES (2, 5, 'y') ON CONFLICT (id) DO UPDATE SET n = n + excluded.n",
+            "INSERT INTO t (id, n) VALUES (4, 40), (4, 1) ON CONFLICT DO UPDATE SET n = excluded.n * 2",
+            "REPLACE INTO t VALUES (3, 33, 'r')",
+        ]);
+        assert_eq!(
+            run(&mut database, "SELECT * FROM t").unwrap(),
+            "id=1, n=10, s=a | id=2, n=25, s=b | id=3, n=33, s=r | id=4, n=2, s=NULL"
+        );
+
+        let failing = [
+            "INSERT INTO t VALUES (1, 1, 'z')",
+            "INSERT INTO t VALUES (1, 1, 'z') ON CONFLICT (n) DO NOTHING",
+            "INSERT INTO t VALUES (1, 1, 'z') ON CONFLICT DO UPDATE SET id = 7",
+            "INSERT INTO t VALUES (1, 1, 'z') ON CONFLICT DO UPDATE SET n = 'text'",
+            "INSERT INTO t VALUES (1, 1, 'z') ON CONFLICT DO UPDATE SET s = zzz",
+        ];
+        for statement in failing {
+            assert!(run(&mut database, statement).is_err(), "{}", statement);
+        }
+        assert!(matches!(
+            run(&mut database, failing[1]),
+            Err(CustomError::ConflictError(_))
+        ));
+        assert!(matches!(
+            run(&mut database, failing[2]),
+            Err(CustomError::KeyFieldModification(_))
+        ));
+        assert!(matches!(
+            run(&mut database, failing[4]),
+            Err(CustomError::UnknownField(_))
+        ));
+        assert_eq!(
+            run(&mut database, "SELECT n FROM t WHERE id = 1").unwrap(),
+            "n=10"
+        );
+    }
+
+    #[test]
+    fn explain_names_the_scan_it_would_use() {
+        let mut database = database(&["CREATE t KEY id FIELDS id: Int, n: Int"]);
+        let cases = [
+            ("id = 1", "Key lookup on t"),
+            ("id > 1 AND id < 3", "Key range scan on t"),
+            ("id > 3 AND id < 1", "Empty scan on t"),
+            ("n = 1 OR id = 1", "Full scan on t"),
+            (
+                "id < 2 AND n IN (SELECT n FROM t)",
+                "Key range scan on t: id < 2",
             ),
+        ];
+        for (condition, scan) in cases {
+            let statement = format!("EXPLAIN SELECT * FROM t WHERE {}", condition);
+            let plan = run(&mut database, &statement).unwrap();
+            assert!(plan.contains(scan), "{}: {}", condition, plan);
+        }
+    }
+
+    #[test]
+    fn quoted_text_fills_only_string_fields() {
+        let mut database = database(&[
+            "CREATE t KEY id FIELDS id: Int, s: String, f: Float, b: Bool",
+            "INSERT INTO t VALUES (1, '1', 1, TRUE)",
+            "INSERT id = 2, s = 2, f = 2.5, b = False INTO t",
+        ]);
+        for statement in [
+            "INSERT INTO t VALUES ('3', 'x', 1, true)",
+            "INSERT id = 3, f = '1.5' INTO t",
+            "INSERT INTO t VALUES (3, 'x', 1, 'true')",
+            "UPDATE t SET f = '1'",
+            "ALTER TABLE t ADD COLUMN n: Int DEFAULT '0'",
+        ] {
+            let result = run(&mut database, statement);
+            assert!(
+                matches!(result, Err(CustomError::TypeError(_))),
+                "{}: {:?}",
+                statement,
+                result
+            );
+        }
+        let result = run(&mut database, "SELECT id FROM t WHERE id = '1'");
+        assert!(matches!(result, Err(CustomError::TypeError(_))));
+        assert_eq!(
+            run(&mut database, "SELECT * FROM t WHERE s = 2 OR b = TRUE").unwrap(),
+            "id=1, s=1, f=1, b=true | id=2, s=2, f=2.5, b=false"
+        );
+    }
+
+    // The library seed once kept in main.rs: text with ':', ',' and '=' goes in quoted
+    // and SAVE_AS/READ_FROM reads the same rows back.
+    #[test]
+    fn library_round_trips_through_a_saved_file() {
+        let mut database = Database::<String>::new();
+        let mut history = CommandHistory::new();
+        for statement in [
+            "CREATE library KEY id FIELDS id: String, title: String, year: Int, pages: Int, rating: Float, topic: String, is_foundational: Bool",
+            r#"INSERT id = "lib1", title = "Homotopy Type Theory: Univalent Foundations", year = 2013, pages = 600, rating = 4.8, topic = "Foundations", is_foundational = true INTO library"#,
+            "INSERT id = 'lib2', title = 'Introduction to HoTT, 2nd ed. (a = b)', year = 2018, pages = 320, rating = 4.2, topic = 'Introductory', is_foundational = false INTO library",
+            "INSERT INTO library VALUES ('lib3', 'Cubical Type Theory Notes', 2020, 210, 4.5, 'Cubical', false)",
+        ] {
+            run_logged(&mut database, &mut history, statement).unwrap();
+        }
+        assert_eq!(
+            run(&mut database, "SELECT title FROM library WHERE id = 'lib1'").unwrap(),
+            "title=Homotopy Type Theory: Univalent Foundations"
+        );
+
+        let path = env::temp_dir().join(format!("library-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+        run_logged(&mut database, &mut history, &format!("SAVE_AS {}", path)).unwrap();
+        let mut read = Database::<String>::new();
+        let result = run(&mut read, &format!("READ_FROM {}", path));
+        fs::remove_file(path).unwrap();
+        result.unwrap();
+        let select = "SELECT * FROM library";
+        assert_eq!(
+            run(&mut read, select).unwrap(),
+            run(&mut database, select).unwrap()
+        );
+    }
+
+    // Replaying the history on an empty database has to rebuild the same tables.
+    #[test]
+    fn history_replays_to_the_same_database() {
+        let mut database = database(&[]);
+        let mut history = CommandHistory::new();
+        let statements = [
+            "CREATE t KEY id FIELDS id: Int, name: String, score: Float, ok: Bool",
+            "CREATE u KEY id FIELDS id: Int, tid: Int",
+            "INSERT id = 1, name = Rome, score = 1.5, ok = true INTO t",
+            "INSERT INTO t VALUES (2, 'it''s \"x\"\\n', 2, false), (3, NULL, 3.5, true)",
+            "INSERT INTO u SELECT id, id + 1 FROM t WHERE id < 3",
+            "INSERT INTO t VALUES (1, 'a', 0.5, true) ON CONFLICT (id) DO UPDATE SET score = score + excluded.score",
+            "INSERT INTO t VALUES (2, 'b', 0.5, true) ON CONFLICT DO NOTHING",
+            "REPLACE INTO u (id, tid) VALUES (2, 9)",
+            "UPDATE t SET name = 'r' WHERE id = 3",
+            "ALTER TABLE t ADD COLUMN extra: Int DEFAULT 7",
+            "ALTER TABLE t RENAME COLUMN extra TO more",
+            "DELETE FROM u WHERE tid = 9",
+            "SELECT * FROM t",
+            "CREATE v KEY id FIELDS id: Int",
+            "DROP TABLE v",
+        ];
+        for statement in statements {
+            run_logged(&mut database, &mut history, statement).unwrap();
+        }
+        assert_eq!(history.list().len(), statements.len());
+
+        let mut replayed = replay(&history);
+        for select in ["SELECT * FROM t", "SELECT * FROM u"] {
+            let expected = run(&mut database, select).unwrap();
+            assert_eq!(run(&mut replayed, select).unwrap(), expected);
+        }
+        assert!(replayed.get_table("v").is_err());
+    }
+
+    #[test]
+    fn update_checks_types_and_key_collisions() {
+        let mut database = database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int, s: String",
+            "INSERT INTO t VALUES (1, 10, 'a'), (2, 20, 'b'), (3, 30, 'c')",
+        ]);
+        assert_eq!(
+            run(&mut database, "UPDATE t SET n = 0, s = 'z' WHERE id > 1").unwrap(),
+            "Data updated succesfully, 2 records affected"
+        );
+        assert_eq!(
+            run(&mut database, "UPDATE t SET n = 5 WHERE id = 7").unwrap(),
+            "Data updated succesfully, 0 records affected"
+        );
+        for failing in [
+            "UPDATE t SET n = 'x'",
+            "UPDATE t SET zz = 1",
+            "UPDATE t SET id = 2 WHERE id = 1",
+        ] {
+            assert!(run(&mut database, failing).is_err(), "{}", failing);
+        }
+        assert_eq!(
+            run(&mut database, "UPDATE t SET id = 9 WHERE id = 1").unwrap(),
+            "Data updated succesfully, 1 records affected"
+        );
+        assert_eq!(
+            run(&mut database, "SELECT * FROM t").unwrap(),
+            "id=2, n=0, s=z | id=3, n=0, s=z | id=9, n=10, s=a"
+        );
+    }
+
+    #[test]
+    fn drop_and_truncate_replay_from_history() {
+        let mut database = database(&[]);
+        let mut history = CommandHistory::new();
+        for statement in [
+            "CREATE t KEY id FIELDS id: Int, n: Int",
+            "CREATE u KEY id FIELDS id: Int",
+            "INSERT INTO t VALUES (1, 10), (2, 20)",
+            "INSERT INTO u VALUES (1)",
+            "DROP TABLE u",
+        ] {
+            run_logged(&mut database, &mut history, statement).unwrap();
+        }
+        assert_eq!(
+            run_logged(&mut database, &mut history, "TRUNCATE t").unwrap(),
+            "Table truncated succesfully, 2 records removed"
+        );
+        run_logged(&mut database, &mut history, "INSERT INTO t VALUES (3, 30)").unwrap();
+        assert!(matches!(
+            run_logged(&mut database, &mut history, "DROP TABLE u"),
+            Err(CustomError::TableNotFound(_))
+        ));
+        assert_eq!(history.list().len(), 7);
+
+        let mut replayed = replay(&history);
+        assert_eq!(run(&mut replayed, "SELECT * FROM t").unwrap(), "id=3, n=30");
+        assert!(replayed.get_table("u").is_err());
+    }
+
+    #[test]
+    fn alter_adds_drops_and_renames_columns() {
+        let mut database = database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int, s: String",
+            "INSERT INTO t VALUES (1, 10, 'a'), (2, 20, NULL)",
+            "ALTER TABLE t ADD COLUMN f: Float DEFAULT 1.5",
+            "ALTER TABLE t ADD COLUMN b: Bool",
+            "ALTER TABLE t RENAME COLUMN s TO name",
+            "ALTER TABLE t DROP COLUMN n",
+        ]);
+        assert_eq!(
+            run(&mut database, "SELECT * FROM t").unwrap(),
+            "id=1, name=a, f=1.5, b=NULL | id=2, name=NULL, f=1.5, b=NULL"
+        );
+        for failing in [
+            "ALTER TABLE t DROP COLUMN id",
+            "ALTER TABLE t RENAME COLUMN f TO name",
+            "ALTER TABLE t ADD COLUMN f: Int",
+            "ALTER TABLE t ADD COLUMN g: Int DEFAULT 'x'",
+            "ALTER TABLE t DROP COLUMN n",
+        ] {
+            assert!(run(&mut database, failing).is_err(), "{}", failing);
         }
+        assert!(run(&mut database, "SELECT n FROM t").is_err());
+
+        // Renaming the key keeps it the key.
+        run(&mut database, "ALTER TABLE t RENAME COLUMN id TO k").unwrap();
+        assert!(run(&mut database, "INSERT INTO t (k) VALUES (1)").is_err());
+        assert_eq!(
+            run(&mut database, "SELECT k, f FROM t WHERE k = 2").unwrap(),
+            "k=2, f=1.5"
+        );
     }
 }
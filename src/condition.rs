@@ -1,4 +1,16 @@
-use crate::database::Value;
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display, Formatter},
+    mem,
+    ops::Bound,
+};
+
+use regex::Regex;
+
+use crate::{
+    database::{Record, Value},
+    expression::Scalar,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Op {
@@ -8,12 +20,28 @@ pub enum Op {
     Le, // <=
     Gt, // >
     Ge, // >=
+    Like,
+    ILike,
+    In,
+    Between,
+    IsNull,
+    IsNotNull,
+    Regexp, // ~
 }
 
 pub struct Condition {
-    pub field_name: String,
+    pub left: Scalar,
     pub op: Op,
-    pub value: Value,
+    pub values: Vec<Scalar>,
+    pub regex: Option<Regex>,
+}
+
+pub enum Predicate {
+    Condition(Condition),
+    Constant(bool),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
 }
 
 impl Op {
@@ -25,69 +53,217 @@ impl Op {
             "<=" => Some(Op::Le),
             ">" => Some(Op::Gt),
             ">=" => Some(Op::Ge),
+            "LIKE" => Some(Op::Like),
+            "ILIKE" => Some(Op::ILike),
+            "IN" => Some(Op::In),
+            "BETWEEN" => Some(Op::Between),
+            "IS NULL" => Some(Op::IsNull),
+            "IS NOT NULL" => Some(Op::IsNotNull),
+            "~" | "REGEXP" => Some(Op::Regexp),
             _ => None,
         }
     }
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Like => "LIKE",
+            Op::ILike => "ILIKE",
+            Op::In => "IN",
+            Op::Between => "BETWEEN",
+            Op::IsNull => "IS NULL",
+            Op::IsNotNull => "IS NOT NULL",
+            Op::Regexp => "~",
+        }
+    }
+
     pub fn get_options() -> Vec<String> {
-        ["=", "!=", "<", "<=", ">", ">="]
-            .iter()
-            .map(|v| v.to_string())
-            .collect()
+        [
+            "=",
+            "!=",
+            "<",
+            "<=",
+            ">",
+            ">=",
+            "LIKE",
+            "NOT LIKE",
+            "ILIKE",
+            "NOT ILIKE",
+            "IN",
+            "NOT IN",
+            "BETWEEN",
+            "NOT BETWEEN",
+            "IS NULL",
+            "IS NOT NULL",
+            "~",
+            "NOT ~",
+        ]
+        .iter()
+        .map(|v| v.to_string())
+        .collect()
     }
 
+    // Int and Float compare numerically; other mismatched types are only ever unequal.
     pub fn cmp(&self, v1: &Value, v2: &Value) -> bool {
+        let numeric = v1.as_f64().is_some() && v2.as_f64().is_some();
+        let ordering = match numeric || mem::discriminant(v1) == mem::discriminant(v2) {
+            true => Some(v1.compare(v2)),
+            false => None,
+        };
         match self {
-            Op::Eq => v1 == v2,
-            Op::Ne => v1 != v2,
-            Op::Lt => match (v1, v2) {
-                (Value::Int(a), Value::Int(b)) => a < b,
-                (Value::Float(a), Value::Float(b)) => a < b,
-                (Value::String(a), Value::String(b)) => a < b,
-                _ => false, // mismatched types cannot be compared
-            },
-            Op::Le => match (v1, v2) {
-                (Value::Int(a), Value::Int(b)) => a <= b,
-                (Value::Float(a), Value::Float(b)) => a <= b,
-                (Value::String(a), Value::String(b)) => a <= b,
+            Op::Eq => ordering.is_some_and(|o| o.is_eq()),
+            Op::Ne => ordering.is_none_or(|o| o.is_ne()),
+            Op::Lt => ordering.is_some_and(|o| o.is_lt()),
+            Op::Le => ordering.is_some_and(|o| o.is_le()),
+            Op::Gt => ordering.is_some_and(|o| o.is_gt()),
+            Op::Ge => ordering.is_some_and(|o| o.is_ge()),
+            Op::Like | Op::ILike => match (v1, v2) {
+                (Value::String(a), Value::String(b)) if *self == Op::ILike => {
+                    like(&a.to_lowercase(), &b.to_lowercase())
+                }
+                (Value::String(a), Value::String(b)) => like(a, b),
                 _ => false,
             },
-            Op::Gt => match (v1, v2) {
-                (Value::Int(a), Value::Int(b)) => a > b,
-                (Value::Float(a), Value::Float(b)) => a > b,
-                (Value::String(a), Value::String(b)) => a > b,
+            // not binary, or needs a compiled pattern; see Condition::matches
+            Op::In | Op::Between | Op::IsNull | Op::IsNotNull | Op::Regexp => false,
+        }
+    }
+}
+
+// `%` matches any run of characters and `_` exactly one.
+fn like(text: &str, pattern: &str) -> bool {
+    let text = text.chars().collect::<Vec<_>>();
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some('_') => {
+                t += 1;
+                p += 1;
+            }
+            Some(c) if *c == text[t] => {
+                t += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    backtrack = Some((bp, bt + 1));
+                    p = bp + 1;
+                    t = bt + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+impl Condition {
+    fn range_of(&self, field: &str) -> (Bound<Value>, Bound<Value>) {
+        let unbounded = (Bound::Unbounded, Bound::Unbounded);
+        if !matches!(&self.left, Scalar::Field(name) if name == field) {
+            return unbounded;
+        }
+        let constants = self
+            .values
+            .iter()
+            .map(|v| match v {
+                Scalar::Constant(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let Some(constants) = constants else {
+            return unbounded;
+        };
+        match (&self.op, constants.as_slice()) {
+            (Op::Eq, [v]) => (Bound::Included(v.clone()), Bound::Included(v.clone())),
+            (Op::Lt, [v]) => (Bound::Unbounded, Bound::Excluded(v.clone())),
+            (Op::Le, [v]) => (Bound::Unbounded, Bound::Included(v.clone())),
+            (Op::Gt, [v]) => (Bound::Excluded(v.clone()), Bound::Unbounded),
+            (Op::Ge, [v]) => (Bound::Included(v.clone()), Bound::Unbounded),
+            (Op::Between, [low, high]) => {
+                (Bound::Included(low.clone()), Bound::Included(high.clone()))
+            }
+            _ => unbounded,
+        }
+    }
+
+    // NULL, a missing field included, only matches IS NULL and compares false otherwise.
+    pub fn matches(&self, record: &Record) -> bool {
+        let Some(val) = self.left.evaluate(record) else {
+            return self.op == Op::IsNull;
+        };
+        let values = self.values.iter().map(|v| v.evaluate(record));
+        match self.op {
+            Op::IsNull => false,
+            Op::IsNotNull => true,
+            Op::In => values.flatten().any(|v| Op::Eq.cmp(&val, &v)),
+            Op::Between => match values.collect::<Vec<_>>().as_slice() {
+                [Some(low), Some(high)] => Op::Ge.cmp(&val, low) && Op::Le.cmp(&val, high),
                 _ => false,
             },
-            Op::Ge => match (v1, v2) {
-                (Value::Int(a), Value::Int(b)) => a >= b,
-                (Value::Float(a), Value::Float(b)) => a >= b,
-                (Value::String(a), Value::String(b)) => a >= b,
+            Op::Regexp => match (&self.regex, &val) {
+                (Some(regex), Value::String(s)) => regex.is_match(s),
                 _ => false,
             },
+            _ => values.take(1).flatten().any(|v| self.op.cmp(&val, &v)),
         }
     }
 }
-impl Condition {
-    pub fn new(
-        field_name: impl Into<String>,
-        op: Op,
-        raw_value: &str,
-        field_type: &str,
-    ) -> Result<Self, String> {
-        let value = match Value::from_string(raw_value, field_type) {
-            Some(v) => v,
-            None => {
-                return Err(format!(
-                    "Cannot parse value '{}' as type '{}'",
-                    raw_value, field_type
+impl Predicate {
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Predicate::Condition(c) => c.matches(record),
+            Predicate::Constant(b) => *b,
+            Predicate::And(l, r) => l.matches(record) && r.matches(record),
+            Predicate::Or(l, r) => l.matches(record) || r.matches(record),
+            Predicate::Not(p) => !p.matches(record),
+        }
+    }
+
+    // The narrowest range of `field` this can match, from comparisons of the field with
+    // constants joined by AND. Anything else leaves the range open on that side.
+    pub fn range_of(&self, field: &str) -> (Bound<Value>, Bound<Value>) {
+        match self {
+            Predicate::Condition(c) => c.range_of(field),
+            Predicate::And(l, r) => {
+                let (l_low, l_high) = l.range_of(field);
+                let (r_low, r_high) = r.range_of(field);
+                (
+                    tighter(l_low, r_low, Ordering::Greater),
+                    tighter(l_high, r_high, Ordering::Less),
                 )
-                .to_string());
             }
-        };
+            _ => (Bound::Unbounded, Bound::Unbounded),
+        }
+    }
+}
 
-        Ok(Self {
-            field_name: field_name.into(),
-            op,
-            value,
-        })
+// Of two bounds on the same side, the one further `toward` the inside of the range.
+fn tighter(a: Bound<Value>, b: Bound<Value>, toward: Ordering) -> Bound<Value> {
+    match (&a, &b) {
+        (Bound::Unbounded, _) => b,
+        (_, Bound::Unbounded) => a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            match x.compare(y) {
+                Ordering::Equal if matches!(a, Bound::Excluded(_)) => a,
+                Ordering::Equal => b,
+                ordering if ordering == toward => a,
+                _ => b,
+            }
+        }
     }
 }
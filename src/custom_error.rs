@@ -42,10 +42,42 @@ pub enum CustomError {
     UnknownCommand(String),
     #[error("Unknown Field: {0}")]
     UnknownField(String),
+    #[error("Ambiguous Field: {0}")]
+    AmbiguousField(String),
+    #[error("Invalid regular expression '{0}': {1}")]
+    InvalidPattern(String, String),
+    #[error("Invalid set operation: {0}")]
+    SetOperationError(String),
+    #[error("Invalid subquery: {0}")]
+    SubqueryError(String),
+    #[error("Invalid window function: {0}")]
+    WindowError(String),
+    #[error("Invalid parameter: {0}")]
+    ParameterError(String),
+    #[error("Invalid ON CONFLICT clause: {0}")]
+    ConflictError(String),
+    #[error("Invalid join: {0}")]
+    JoinError(String),
+    #[error("Field already exists: {0}")]
+    FieldAlreadyExists(String),
+    #[error("Unknown Type: {0}")]
+    UnknownType(String),
+    #[error("Type error: {0}")]
+    TypeError(String),
+    #[error("Invalid aggregate: {0}")]
+    InvalidAggregate(String),
+    #[error("Key field cannot be modified: {0}")]
+    KeyFieldModification(String),
     #[error("Invalid index: {0}")]
     InvalidIndex(usize),
     #[error("Invalid Key Type")]
     WrongKeyType(),
     #[error("Table Name not present")]
     TableNameNotPresent(),
+    #[error("Syntax error at line {line}, column {column}: {message}")]
+    SyntaxError {
+        message: String,
+        line: usize,
+        column: usize,
+    },
 }
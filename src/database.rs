@@ -1,10 +1,21 @@
 use std::{
     any::Any,
-    collections::{BTreeMap, HashMap, btree_map::Entry},
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    mem,
+    ops::Bound,
 };
 
-use crate::{custom_error::CustomError, parsers::parse_conditions};
+use crate::{
+    ast::{Assignment, Column, Expr, Literal, Operand, SelectStatement},
+    condition::Predicate,
+    custom_error::CustomError,
+    parsers::{parse_fields, parse_predicate},
+    prepared::PreparedStatement,
+    query::{ResultSet, Stage, bind_condition, scope_to, select_rows},
+};
 
 pub trait DatabaseKey: Ord {
     fn is_equal_to(&self, other: &Self) -> bool;
@@ -16,7 +27,7 @@ pub trait DatabaseKey: Ord {
     fn key_type_name() -> &'static str;
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     String(String),
@@ -32,10 +43,13 @@ pub struct Table<K: DatabaseKey> {
     pub table_name: String,
     pub key_field: String,
     pub fields: HashMap<String, String>,
+    pub columns: Vec<String>,
     pub records: BTreeMap<K, Record>,
 }
 pub struct Database<K: DatabaseKey> {
     pub tables: HashMap<String, Table<K>>,
+    // Statements stored by PREPARE, by name.
+    pub prepared: HashMap<String, PreparedStatement>,
 }
 
 pub enum AnyDatabase {
@@ -61,12 +75,8 @@ impl AnyDatabase {
     }
     pub fn get_fields(&self, table_name: &str) -> Result<Vec<String>, CustomError> {
         let result = match self {
-            AnyDatabase::StringDatabase(db) => {
-                db.get_table(table_name)?.fields.keys().cloned().collect()
-            }
-            AnyDatabase::IntDatabase(db) => {
-                db.get_table(table_name)?.fields.keys().cloned().collect()
-            }
+            AnyDatabase::StringDatabase(db) => db.get_table(table_name)?.columns.clone(),
+            AnyDatabase::IntDatabase(db) => db.get_table(table_name)?.columns.clone(),
         };
         Ok(result)
     }
@@ -85,6 +95,62 @@ impl Value {
         }
     }
 
+    // Quoted text is only ever a String. A bare TRUE or FALSE is a Bool in any case.
+    pub fn from_literal(literal: &Literal, type_hint: &str) -> Option<Self> {
+        let string = type_hint.trim().eq_ignore_ascii_case("string");
+        match literal {
+            Literal::Quoted(s) => string.then(|| Value::String(s.to_string())),
+            Literal::Word(w) if !string => Value::from_string(&w.to_ascii_lowercase(), type_hint),
+            Literal::Number(s) | Literal::Word(s) => Value::from_string(s, type_hint),
+            Literal::Parameter(_) => None,
+        }
+    }
+
+    pub fn is_known_type(type_hint: &str) -> bool {
+        matches!(
+            type_hint.trim().to_ascii_uppercase().as_str(),
+            "INT" | "FLOAT" | "STRING" | "BOOL"
+        )
+    }
+
+    // Bools sort before numbers, numbers before strings; Int and Float compare numerically.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Int(a), Value::Float(b)) => compare_int_float(*a, *b),
+            (Value::Float(a), Value::Int(b)) => compare_int_float(*b, *a).reverse(),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Bool(_) => 0,
+            Value::Int(_) | Value::Float(_) => 1,
+            Value::String(_) => 2,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+        }
+    }
+
     pub fn as_string(&self) -> String {
         match self {
             Value::Bool(b) => b.to_string(),
@@ -94,6 +160,42 @@ impl Value {
         }
     }
 }
+// Values of different types are never equal; floats compare by bit pattern so that
+// Value can be used as a hash key, e.g. for DISTINCT and set operations.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        mem::discriminant(self) == mem::discriminant(other) && self.compare(other).is_eq()
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Value::Bool(b) => b.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Int(i) => i.hash(state),
+            Value::Float(f) => f.to_bits().hash(state),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Same as compare, except that an Int sorts before a Float of equal value.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare(other)
+            .then_with(|| matches!(self, Value::Float(_)).cmp(&matches!(other, Value::Float(_))))
+    }
+}
+
 impl Record {
     pub fn new() -> Self {
         Self {
@@ -106,10 +208,17 @@ impl Record {
     }
 }
 
+impl Default for Record {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for Record {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let s = self
-            .values
+        let mut values = self.values.iter().collect::<Vec<_>>();
+        values.sort_by(|a, b| a.0.cmp(b.0));
+        let s = values
             .iter()
             .map(|(key, value)| format!("{}={}", key, value.as_string()))
             .collect::<Vec<_>>()
@@ -121,14 +230,16 @@ impl<K: DatabaseKey> Database<K> {
     pub fn new() -> Self {
         Self {
             tables: HashMap::new(),
+            prepared: HashMap::new(),
         }
     }
     pub fn create_table(
         &mut self,
         name: String,
         key_field: String,
-        fields: HashMap<String, String>,
+        columns: &[(String, String)],
     ) -> Result<(), CustomError> {
+        let fields = parse_fields(columns)?;
         if self.tables.contains_key(&name) {
             return Err(CustomError::TableAlreadyExists(name.to_string()));
         }
@@ -142,10 +253,29 @@ impl<K: DatabaseKey> Database<K> {
             return Err(CustomError::WrongKeyType());
         }
 
-        let table = Table::new(&name, key_field, fields);
+        let columns = columns.iter().map(|(name, _)| name.to_string()).collect();
+        let table = Table::new(&name, key_field, fields, columns);
         self.tables.insert(name, table);
         Ok(())
     }
+    pub fn drop_table(&mut self, table_name: &str) -> Result<(), CustomError> {
+        match self.tables.remove(table_name) {
+            Some(_) => Ok(()),
+            None => Err(CustomError::TableNotFound(table_name.to_string())),
+        }
+    }
+    pub fn rename_table(&mut self, table_name: &str, new_name: &str) -> Result<(), CustomError> {
+        if self.tables.contains_key(new_name) {
+            return Err(CustomError::TableAlreadyExists(new_name.to_string()));
+        }
+        let mut table = self
+            .tables
+            .remove(table_name)
+            .ok_or_else(|| CustomError::TableNotFound(table_name.to_string()))?;
+        table.table_name = new_name.to_string();
+        self.tables.insert(new_name.to_string(), table);
+        Ok(())
+    }
     pub fn get_table_mut(&mut self, table_name: &str) -> Result<&mut Table<K>, CustomError> {
         self.tables
             .get_mut(table_name)
@@ -158,6 +288,12 @@ impl<K: DatabaseKey> Database<K> {
     }
 }
 
+impl<K: DatabaseKey> Default for Database<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DatabaseKey for String {
     fn is_equal_to(&self, other: &Self) -> bool {
         self == other
@@ -200,32 +336,53 @@ impl DatabaseKey for i64 {
     }
 }
 impl<K: DatabaseKey> Table<K> {
-    fn new(table_name: &str, key_field: String, fields: HashMap<String, String>) -> Self {
+    fn new(
+        table_name: &str,
+        key_field: String,
+        fields: HashMap<String, String>,
+        columns: Vec<String>,
+    ) -> Self {
         Self {
             table_name: table_name.to_string(),
             key_field,
             fields,
+            columns,
             records: BTreeMap::new(),
         }
     }
 
-    pub fn add_record(&mut self, record: Record) -> Result<(), CustomError> {
-        let key_value = match record.values.get(&self.key_field) {
-            Some(k) => k,
-            None => {
-                return Err(CustomError::MissingField(self.key_field.to_string()));
-            }
-        };
-
-        let k_key: K = K::from_value(key_value)?;
+    // Adds every record, or none of them when one fails. A record whose key is taken, by
+    // the table or by an earlier record of the same batch, is passed to `resolve` with the
+    // record it collides with; it returns the record to keep, or None to leave the old one.
+    pub fn add_records(
+        &mut self,
+        records: Vec<Record>,
+        resolve: impl Fn(&Record, Record) -> Result<Option<Record>, CustomError>,
+    ) -> Result<usize, CustomError> {
+        let mut added = BTreeMap::new();
+        let mut count = 0;
+        for record in records {
+            let key_value = match record.values.get(&self.key_field) {
+                Some(k) => k,
+                None => {
+                    return Err(CustomError::MissingField(self.key_field.to_string()));
+                }
+            };
 
-        match self.records.entry(k_key) {
-            Entry::Vacant(entry) => {
-                entry.insert(record);
-                Ok(())
-            }
-            Entry::Occupied(_) => Err(CustomError::RecordAlreadyExists(record.to_string())),
+            let k_key: K = K::from_value(key_value)?;
+            let record = match added.get(&k_key).or_else(|| self.records.get(&k_key)) {
+                Some(existing) => match resolve(existing, record)? {
+                    Some(record) => record,
+                    None => continue,
+                },
+                None => record,
+            };
+            added.insert(k_key, record);
+            count += 1;
         }
+
+        self.records.extend(added);
+        Ok(count)
     }
 
     pub fn remove_record(&mut self, key_str: &str) -> Result<(), CustomError> {
@@ -242,68 +399,482 @@ impl<K: DatabaseKey> Table<K> {
             None => Err(CustomError::InvalidKey(key_str.to_string())),
         }
     }
-    pub fn parse_record(&mut self, record_str: &str) -> Result<Record, CustomError> {
-        let mut record = Record::new();
+    pub fn add_column(
+        &mut self,
+        name: &str,
+        typ: &str,
+        default: Option<&Literal>,
+    ) -> Result<(), CustomError> {
+        if self.fields.contains_key(name) {
+            return Err(CustomError::FieldAlreadyExists(name.to_string()));
+        }
+        if !Value::is_known_type(typ) {
+            return Err(CustomError::UnknownType(typ.to_string()));
+        }
+        let default = match default {
+            Some(literal) => Some(field_value(name, typ, literal)?),
+            None => None,
+        };
 
-        for part in record_str.split(',') {
-            let trimmed = part.trim();
-            if trimmed.is_empty() {
-                continue;
+        if let Some(value) = default {
+            for record in self.records.values_mut() {
+                record.values.insert(name.to_string(), value.clone());
             }
+        }
+        self.fields.insert(name.to_string(), typ.to_string());
+        self.columns.push(name.to_string());
+        Ok(())
+    }
 
-            let mut kv = trimmed.split('=');
-            let (Some(name), Some(value)) = (kv.next(), kv.next()) else {
-                return Err(CustomError::FieldParseError(trimmed.to_string()));
-            };
+    pub fn drop_column(&mut self, name: &str) -> Result<(), CustomError> {
+        if name == self.key_field {
+            return Err(CustomError::KeyFieldModification(name.to_string()));
+        }
+        if self.fields.remove(name).is_none() {
+            return Err(CustomError::UnknownField(name.to_string()));
+        }
+        self.columns.retain(|c| c != name);
+        for record in self.records.values_mut() {
+            record.values.remove(name);
+        }
+        Ok(())
+    }
 
+    pub fn rename_column(&mut self, from: &str, to: &str) -> Result<(), CustomError> {
+        if self.fields.contains_key(to) {
+            return Err(CustomError::FieldAlreadyExists(to.to_string()));
+        }
+        let typ = self
+            .fields
+            .remove(from)
+            .ok_or_else(|| CustomError::UnknownField(from.to_string()))?;
+        self.fields.insert(to.to_string(), typ);
+        for column in self.columns.iter_mut().filter(|c| *c == from) {
+            *column = to.to_string();
+        }
+        if self.key_field == from {
+            self.key_field = to.to_string();
+        }
+        for record in self.records.values_mut() {
+            if let Some(value) = record.values.remove(from) {
+                record.values.insert(to.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    // Removes every record matching `condition`, or all of them without one. Comparing
+    // the key with constants limits the scan to that range of keys.
+    pub fn delete_records(&mut self, condition: &Option<Expr>) -> Result<usize, CustomError> {
+        let Some(predicate) = bind_condition(condition, &self.fields)? else {
+            return Ok(self.truncate());
+        };
+        let Some(range) = self.key_range(Some(&predicate)) else {
+            return Ok(0);
+        };
+        Ok(self
+            .records
+            .extract_if(range, |_, record| predicate.matches(record))
+            .count())
+    }
+
+    // The keys a condition can match: all of them, a range, or None when no key can.
+    pub fn key_range(&self, predicate: Option<&Predicate>) -> Option<(Bound<K>, Bound<K>)> {
+        let Some(predicate) = predicate else {
+            return Some((Bound::Unbounded, Bound::Unbounded));
+        };
+        // A bound the key type cannot hold, like `id < 2.5` on Int keys, is left open;
+        // the predicate still decides for each record in the range.
+        let to_key = |bound: Bound<Value>| match bound {
+            Bound::Included(v) => K::from_value(&v).map_or(Bound::Unbounded, Bound::Included),
+            Bound::Excluded(v) => K::from_value(&v).map_or(Bound::Unbounded, Bound::Excluded),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let (low, high) = predicate.range_of(&self.key_field);
+        let range = (to_key(low), to_key(high));
+        // BTreeMap panics on a range that ends before it starts.
+        let empty = match &range {
+            (Bound::Included(a), Bound::Included(b)) => a > b,
+            (Bound::Included(a) | Bound::Excluded(a), Bound::Included(b) | Bound::Excluded(b)) => {
+                a >= b
+            }
+            _ => false,
+        };
+        (!empty).then_some(range)
+    }
+
+    pub fn scan(
+        &self,
+        range: Option<(Bound<K>, Bound<K>)>,
+    ) -> impl DoubleEndedIterator<Item = &Record> {
+        range
+            .into_iter()
+            .flat_map(|range| self.records.range(range))
+            .map(|(_, record)| record)
+    }
+
+    // Some(descending) when the records come out of the BTreeMap already in the order
+    // asked for, so the scan can stop as soon as the page is full; any other ordering,
+    // grouping or window needs every match first.
+    pub fn key_order(&self, statement: &SelectStatement) -> Option<bool> {
+        if statement.is_grouped() || !statement.windows().is_empty() {
+            return None;
+        }
+        match statement.order_by.as_slice() {
+            [] => Some(false),
+            [key] if key.operand == Operand::Column(Column::Field(self.key_field.to_string())) => {
+                Some(key.descending)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn truncate(&mut self) -> usize {
+        let count = self.records.len();
+        self.records.clear();
+        count
+    }
+    pub fn parse_record(&self, assignments: &[Assignment]) -> Result<Record, CustomError> {
+        let mut record = Record::new();
+
+        for assignment in assignments {
             let field_type = self
                 .fields
-                .get(name.trim())
-                .ok_or_else(|| CustomError::FieldParseError(name.to_string()))?;
+                .get(&assignment.field)
+                .ok_or_else(|| CustomError::FieldParseError(assignment.field.to_string()))?;
 
-            let value = Value::from_string(value.trim(), field_type)
-                .ok_or_else(|| CustomError::FieldParseError(name.to_string()))?;
+            let value = field_value(&assignment.field, field_type, &assignment.value)?;
 
-            record.values.insert(name.trim().to_string(), value);
+            record.values.insert(assignment.field.to_string(), value);
         }
 
         Ok(record)
     }
+    // Reads only the key range the WHERE clause allows. `trace` is as for `select_rows`.
     pub fn select_records(
+        &self,
+        statement: &SelectStatement,
+        trace: &mut impl FnMut(Stage, usize),
+    ) -> Result<ResultSet, CustomError> {
+        let statement = &scope_to(statement, &self.columns)?;
+        let cond = bind_condition(&statement.condition, &self.fields)?;
+        let records = self.scan(self.key_range(cond.as_ref()));
+        let Some(descending) = self.key_order(statement) else {
+            let records = records.collect::<Vec<_>>();
+            trace(Stage::Source, records.len());
+            return select_rows(records, &self.fields, statement, trace);
+        };
+
+        let records: Box<dyn Iterator<Item = &Record>> = if descending {
+            Box::new(records.rev())
+        } else {
+            Box::new(records)
+        };
+        // The stages run together, one record at a time, so they are counted as they go.
+        let (mut scanned, mut matched) = (0, 0);
+        let result = ResultSet::new(
+            statement,
+            &self.fields,
+            records
+                .inspect(|_| scanned += 1)
+                .filter(|r| match &cond {
+                    Some(predicate) => predicate.matches(r),
+                    None => true,
+                })
+                .inspect(|_| matched += 1),
+        )?;
+        trace(Stage::Source, scanned);
+        trace(Stage::Filter, matched);
+        trace(Stage::Output, result.records.len());
+        Ok(result)
+    }
+
+    pub fn update_records(
         &mut self,
-        fields: &[String],
-        conditions_str: &Option<String>,
-    ) -> Result<Vec<Record>, CustomError> {
-        let cond = match conditions_str {
-            Some(s) => Some(parse_conditions(s, &self.fields)?),
+        assignments: &[Assignment],
+        condition: &Option<Expr>,
+    ) -> Result<usize, CustomError> {
+        let changes = self.parse_record(assignments)?;
+        let cond = match condition {
+            Some(expr) => Some(parse_predicate(expr, &self.fields)?),
             None => None,
         };
 
-        let result = self
-            .records
-            .values()
-            .filter(|r| match &cond {
-                Some(cnd) => cnd.iter().any(|c| {
-                    if let Some(val) = r.values.get(&c.field_name) {
-                        c.op.cmp(val, &c.value)
-                    } else {
-                        false
-                    }
-                }),
+        let mut remaining = BTreeMap::new();
+        let mut originals = Vec::new();
+        for (key, record) in mem::take(&mut self.records) {
+            let matched = match &cond {
+                Some(predicate) => predicate.matches(&record),
                 None => true,
+            };
+            if matched {
+                originals.push((key, record));
+            } else {
+                remaining.insert(key, record);
+            }
+        }
+
+        let mut updated = BTreeMap::new();
+        let mut collision = None;
+        for (_, record) in &originals {
+            let mut new_record = record.clone();
+            new_record.values.extend(changes.values.clone());
+
+            let key = new_record
+                .values
+                .get(&self.key_field)
+                .ok_or_else(|| CustomError::MissingField(self.key_field.to_string()))
+                .and_then(K::from_value);
+            match key {
+                Ok(k) if !remaining.contains_key(&k) && !updated.contains_key(&k) => {
+                    updated.insert(k, new_record);
+                }
+                Ok(_) => {
+                    collision = Some(CustomError::RecordAlreadyExists(new_record.to_string()));
+                    break;
+                }
+                Err(e) => {
+                    collision = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let count = originals.len();
+        if let Some(e) = collision {
+            remaining.extend(originals);
+            self.records = remaining;
+            return Err(e);
+        }
+
+        remaining.extend(updated);
+        self.records = remaining;
+        Ok(count)
+    }
+}
+
+// Exact, where `i as f64` would round past 2^53 and make the order intransitive. Around
+// the ints it keeps the order of `total_cmp`: -0.0 sorts below 0 and NaN at either end.
+fn compare_int_float(i: i64, f: f64) -> Ordering {
+    // -2^63 and 2^63, both exact as f64
+    let (min, max) = (i64::MIN as f64, -(i64::MIN as f64));
+    if f.is_nan() {
+        return match f.is_sign_negative() {
+            true => Ordering::Greater,
+            false => Ordering::Less,
+        };
+    }
+    if f >= max {
+        return Ordering::Less;
+    }
+    if f < min {
+        return Ordering::Greater;
+    }
+    let floor = f.floor();
+    match i.cmp(&(floor as i64)) {
+        Ordering::Equal if f > floor => Ordering::Less,
+        Ordering::Equal if i == 0 && f.is_sign_negative() => Ordering::Greater,
+        ordering => ordering,
+    }
+}
+
+// Reads a literal into a field of type `typ`, for INSERT, UPDATE and ALTER ... DEFAULT.
+fn field_value(field: &str, typ: &str, literal: &Literal) -> Result<Value, CustomError> {
+    Value::from_literal(literal, typ).ok_or_else(|| match literal {
+        Literal::Quoted(_) => CustomError::TypeError(format!(
+            "cannot store {} (String) in {} ({})",
+            literal, field, typ
+        )),
+        _ => CustomError::FieldParseError(field.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, collections::HashMap, ops::Bound};
+
+    use crate::{
+        ast::{Query, Statement},
+        custom_error::CustomError,
+        parser::parse,
+        query::bind_condition,
+    };
+
+    use super::{Database, Record, Table, Value};
+
+    fn record(id: i64, n: i64) -> Record {
+        Record::with_values(HashMap::from([
+            ("id".to_string(), Value::Int(id)),
+            ("n".to_string(), Value::Int(n)),
+        ]))
+    }
+
+    fn database() -> Database<i64> {
+        let mut database = Database::new();
+        let fields = [
+            ("id".to_string(), "Int".to_string()),
+            ("n".to_string(), "Int".to_string()),
+        ];
+        database
+            .create_table("t".to_string(), "id".to_string(), &fields)
+            .unwrap();
+        let records = (1..=5).map(|id| record(id, id * 10)).collect();
+        database
+            .get_table_mut("t")
+            .unwrap()
+            .add_records(records, |_, _| unreachable!())
+            .unwrap();
+        database
+    }
+
+    fn values(table: &Table<i64>) -> Vec<(i64, i64)> {
+        table
+            .records
+            .iter()
+            .map(|(id, r)| match r.values.get("n") {
+                Some(Value::Int(n)) => (*id, *n),
+                other => panic!("unexpected n {:?}", other),
             })
-            .map(|r| {
-                let mut r2 = r.clone();
-                r2.values = r
-                    .values
-                    .iter()
-                    .filter(|(k, _v)| fields.contains(&k.to_string_2()))
-                    .map(|(k, v)| (k.to_string(), v.clone()))
-                    .collect();
-                r2
+            .collect()
+    }
+
+    fn where_clause(condition: &str) -> Option<crate::ast::Expr> {
+        match parse(&format!("SELECT * FROM t WHERE {}", condition)) {
+            Ok(Statement::Select(Query::Select(select))) => select.condition,
+            other => panic!("expected a SELECT, got {:?}", other),
+        }
+    }
+
+    fn range(table: &Table<i64>, condition: &str) -> Option<(Bound<i64>, Bound<i64>)> {
+        let predicate = bind_condition(&where_clause(condition), &table.fields).unwrap();
+        table.key_range(predicate.as_ref())
+    }
+
+    #[test]
+    fn key_range_from_comparisons_with_the_key() {
+        let database = database();
+        let table = database.get_table("t").unwrap();
+        use Bound::{Excluded, Included, Unbounded};
+        let cases = [
+            ("id = 3", Some((Included(3), Included(3)))),
+            ("id > 2 AND id <= 4", Some((Excluded(2), Included(4)))),
+            (
+                "id >= 2 AND id > 2 AND id < 9",
+                Some((Excluded(2), Excluded(9))),
+            ),
+            (
+                "id BETWEEN 2 AND 4 AND n > 0",
+                Some((Included(2), Included(4))),
+            ),
+            ("n > 2", Some((Unbounded, Unbounded))),
+            ("id > 2 OR id < 1", Some((Unbounded, Unbounded))),
+            ("NOT id = 3", Some((Unbounded, Unbounded))),
+            ("id < 2.5", Some((Unbounded, Unbounded))),
+            ("id > 4 AND id < 2", None),
+            ("id > 3 AND id < 3", None),
+            ("id >= 3 AND id < 3", None),
+        ];
+        for (condition, expected) in cases {
+            assert_eq!(range(table, condition), expected, "{}", condition);
+        }
+        assert_eq!(table.key_range(None), Some((Unbounded, Unbounded)));
+    }
+
+    #[test]
+    fn delete_counts_only_matching_records() {
+        let mut database = database();
+        let table = database.get_table_mut("t").unwrap();
+        assert_eq!(
+            table
+                .delete_records(&where_clause("id > 4 AND id < 2"))
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            table
+                .delete_records(&where_clause("id >= 2 AND n != 30"))
+                .unwrap(),
+            3
+        );
+        assert_eq!(values(table), [(1, 10), (3, 30)]);
+        assert_eq!(table.delete_records(&where_clause("id < 2.5")).unwrap(), 1);
+        assert_eq!(table.delete_records(&None).unwrap(), 1);
+        assert!(values(table).is_empty());
+    }
+
+    #[test]
+    fn failed_batch_adds_nothing() {
+        let mut database = database();
+        let table = database.get_table_mut("t").unwrap();
+        let before = values(table);
+        let result = table.add_records(vec![record(6, 60), record(3, 0), record(7, 70)], |_, r| {
+            Err(CustomError::RecordAlreadyExists(r.to_string()))
+        });
+        assert!(matches!(result, Err(CustomError::RecordAlreadyExists(_))));
+        assert_eq!(values(table), before);
+
+        let result = table.add_records(vec![record(8, 80), Record::new()], |_, _| Ok(None));
+        assert!(matches!(result, Err(CustomError::MissingField(_))));
+        assert_eq!(values(table), before);
+    }
+
+    #[test]
+    fn later_rows_of_a_batch_see_earlier_ones() {
+        let mut database = database();
+        let table = database.get_table_mut("t").unwrap();
+        let batch = vec![record(6, 1), record(6, 2), record(1, 3)];
+        let count = table
+            .add_records(batch, |existing, mut record| {
+                let total = match (existing.values.get("n"), record.values.get("n")) {
+                    (Some(Value::Int(a)), Some(Value::Int(b))) => a + b,
+                    _ => unreachable!(),
+                };
+                record.values.insert("n".to_string(), Value::Int(total));
+                Ok(Some(record))
             })
-            .collect::<Vec<_>>();
+            .unwrap();
+        assert_eq!(count, 3);
+        let values = values(table);
+        assert_eq!((values[0], values[5]), ((1, 13), (6, 3)));
+    }
 
-        Ok(result)
+    #[test]
+    fn ints_and_floats_compare_exactly() {
+        let two_53 = 9007199254740992;
+        let float = Value::Float(two_53 as f64);
+        assert_eq!(Value::Int(two_53 + 1).cmp(&float), Ordering::Greater);
+        assert_eq!(Value::Int(two_53).compare(&float), Ordering::Equal);
+        assert_eq!(Value::Int(two_53).cmp(&float), Ordering::Less);
+        assert_eq!(
+            Value::Int(i64::MAX).cmp(&Value::Float(i64::MAX as f64)),
+            Ordering::Less
+        );
+
+        let mut values = vec![
+            Value::Int(two_53 + 1),
+            float,
+            Value::Int(two_53),
+            Value::Float(two_53 as f64 + 2.0),
+            Value::Int(i64::MAX),
+            Value::Int(i64::MIN),
+            Value::Float(i64::MAX as f64),
+            Value::Float(i64::MIN as f64),
+            Value::Int(0),
+            Value::Float(0.0),
+            Value::Float(-0.0),
+            Value::Float(-0.5),
+            Value::Int(-1),
+            Value::Float(f64::INFINITY),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(f64::NAN),
+            Value::Float(-f64::NAN),
+        ];
+        values.sort();
+        for (i, a) in values.iter().enumerate() {
+            for b in &values[i..] {
+                assert_ne!(a.cmp(b), Ordering::Greater, "{:?} > {:?}", a, b);
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{:?} {:?}", a, b);
+            }
+        }
     }
 }
@@ -64,67 +64,64 @@ pub fn handle_events(app: &mut App) -> Result<bool, CustomError> {
                     | CurrentScreen::SelectTable
                     | CurrentScreen::SelectField
                     | CurrentScreen::SelectCondition
-                    | CurrentScreen::InputFieldTypePair => {
-                        if app.current_index > 0 {
-                            app.current_index -= 1;
-                        }
+                    | CurrentScreen::InputFieldTypePair
+                        if app.current_index > 0 =>
+                    {
+                        app.current_index -= 1;
                     }
                     _ => {}
                 },
                 KeyCode::Down => match app.current_screen {
-                    CurrentScreen::Main => {
-                        if app.current_index < 5 {
-                            app.current_index += 1;
-                        }
+                    CurrentScreen::Main if app.current_index < 7 => {
+                        app.current_index += 1;
                     }
-                    CurrentScreen::SelectKeyField => {
-                        if app.current_index < app.generic_hashmap.len() - 1 {
-                            app.current_index += 1;
-                        }
+                    CurrentScreen::SelectKeyField
+                        if app.current_index < app.generic_hashmap.len() - 1 =>
+                    {
+                        app.current_index += 1;
                     }
-                    CurrentScreen::SelectTable => {
+                    CurrentScreen::SelectTable
                         if app.current_index
-                            < app.database.get_table_names().len() - app.selected_fields.len() - 1
-                        {
-                            app.current_index += 1;
-                        }
-                    }
-                    CurrentScreen::SelectField => {
-                        if app.current_index < app.possibilities.len() - 1 {
-                            app.current_index += 1;
-                        }
-                    }
-                    CurrentScreen::InputFieldTypePair => {
-                        if app.current_index < app.database.get_possible_types().len() - 1 {
-                            app.current_index += 1;
-                        }
-                    }
-                    CurrentScreen::SelectCondition => {
-                        if app.current_index < Op::get_options().len() - 1 {
-                            app.current_index += 1;
-                        }
+                            < app.database.get_table_names().len()
+                                - app.selected_fields.len()
+                                - 1 =>
+                    {
+                        app.current_index += 1;
+                    }
+                    CurrentScreen::SelectField
+                        if app.current_index < app.possibilities.len() - 1 =>
+                    {
+                        app.current_index += 1;
+                    }
+                    CurrentScreen::InputFieldTypePair
+                        if app.current_index < app.database.get_possible_types().len() - 1 =>
+                    {
+                        app.current_index += 1;
+                    }
+                    CurrentScreen::SelectCondition
+                        if app.current_index < Op::get_options().len() - 1 =>
+                    {
+                        app.current_index += 1;
                     }
                     _ => {}
                 },
                 KeyCode::Right => match app.current_screen {
-                    CurrentScreen::InputFieldValuePair => {
-                        if app.field_or_value == FieldType::Field {
-                            app.field_or_value = FieldType::Value;
-                            app.input_field = Some(app.current_input.to_string());
-                            app.current_input = match &app.input_value {
-                                Some(v) => v.to_string(),
-                                None => "".to_string(),
-                            }
+                    CurrentScreen::InputFieldValuePair
+                        if app.field_or_value == FieldType::Field =>
+                    {
+                        app.field_or_value = FieldType::Value;
+                        app.input_field = Some(app.current_input.to_string());
+                        app.current_input = match &app.input_value {
+                            Some(v) => v.to_string(),
+                            None => "".to_string(),
                         }
                     }
-                    CurrentScreen::SelectCondition => {
-                        if app.field_or_value == FieldType::Field {
-                            app.field_or_value = FieldType::Value;
-                            app.condition_field = Some(app.current_input.to_string());
-                            app.current_input = match &app.condition_value {
-                                Some(v) => v.clone(),
-                                None => "".to_string(),
-                            }
+                    CurrentScreen::SelectCondition if app.field_or_value == FieldType::Field => {
+                        app.field_or_value = FieldType::Value;
+                        app.condition_field = Some(app.current_input.to_string());
+                        app.current_input = match &app.condition_value {
+                            Some(v) => v.clone(),
+                            None => "".to_string(),
                         }
                     }
 
@@ -132,24 +129,22 @@ pub fn handle_events(app: &mut App) -> Result<bool, CustomError> {
                 },
 
                 KeyCode::Left => match app.current_screen {
-                    CurrentScreen::InputFieldValuePair => {
-                        if app.field_or_value == FieldType::Value {
-                            app.field_or_value = FieldType::Field;
-                            app.input_value = Some(app.current_input.clone());
-                            app.current_input = match &app.input_field {
-                                Some(v) => v.clone(),
-                                None => "".to_string(),
-                            }
+                    CurrentScreen::InputFieldValuePair
+                        if app.field_or_value == FieldType::Value =>
+                    {
+                        app.field_or_value = FieldType::Field;
+                        app.input_value = Some(app.current_input.clone());
+                        app.current_input = match &app.input_field {
+                            Some(v) => v.clone(),
+                            None => "".to_string(),
                         }
                     }
-                    CurrentScreen::SelectCondition => {
-                        if app.field_or_value == FieldType::Value {
-                            app.field_or_value = FieldType::Field;
-                            app.condition_value = Some(app.current_input.clone());
-                            app.current_input = match &app.condition_field {
-                                Some(v) => v.clone(),
-                                None => "".to_string(),
-                            }
+                    CurrentScreen::SelectCondition if app.field_or_value == FieldType::Value => {
+                        app.field_or_value = FieldType::Field;
+                        app.condition_value = Some(app.current_input.clone());
+                        app.current_input = match &app.condition_field {
+                            Some(v) => v.clone(),
+                            None => "".to_string(),
                         }
                     }
                     _ => {}
@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    ops::Bound,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{Expr, Join, Query, SelectStatement},
+    custom_error::CustomError,
+    database::{Database, DatabaseKey, Table},
+    join::select_joined,
+    query::{
+        ResultSet, Scope, Stage, bind_condition, execute_recursive, recursive_union, scope_to,
+        select_rows,
+    },
+};
+
+// One step of a query plan, with the steps that feed it as children.
+pub struct Plan {
+    pub operation: String,
+    pub children: Vec<Plan>,
+    // Rows the step passed on and the time it took, under EXPLAIN ANALYZE.
+    pub actual: Option<(usize, Duration)>,
+}
+
+// Describes how `query` would run. With `analyze` it also runs it, stage by stage the
+// way `execute_query` does, and records what each stage did.
+pub fn explain<K: DatabaseKey>(
+    database: &mut Database<K>,
+    query: &Query,
+    analyze: bool,
+) -> Result<Plan, CustomError> {
+    let (plan, _) = Explainer { analyze }.query(database, &Scope::new(), query)?;
+    Ok(plan)
+}
+
+impl Plan {
+    fn new(operation: String) -> Self {
+        Self {
+            operation,
+            children: Vec::new(),
+            actual: None,
+        }
+    }
+
+    fn child(mut self, child: Plan) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{}{}", "  ".repeat(depth), self.operation)?;
+        if let Some((rows, time)) = self.actual {
+            write!(
+                f,
+                "  (actual rows={}, time={:.3} ms)",
+                rows,
+                time.as_secs_f64() * 1000.0
+            )?;
+        }
+        for child in &self.children {
+            writeln!(f)?;
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+// Rows and time of each stage of one select; a stage's time runs from the stage before.
+struct Stages {
+    last: Instant,
+    actual: HashMap<Stage, (usize, Duration)>,
+}
+
+impl Stages {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            actual: HashMap::new(),
+        }
+    }
+
+    fn trace(&mut self, stage: Stage, rows: usize) {
+        let now = Instant::now();
+        self.actual.insert(stage, (rows, now - self.last));
+        self.last = now;
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Explainer {
+    analyze: bool,
+}
+
+impl Explainer {
+    // The result is there only under ANALYZE, when the query has actually run.
+    fn query<K: DatabaseKey>(
+        self,
+        database: &mut Database<K>,
+        scope: &Scope,
+        query: &Query,
+    ) -> Result<(Plan, Option<ResultSet>), CustomError> {
+        match query {
+            Query::Select(select) => self.select(database, scope, select),
+            Query::Compound(compound) => {
+                let (left_plan, left) = self.query(database, scope, &compound.left)?;
+                let (right_plan, right) = self.query(database, scope, &compound.right)?;
+                let mut operation = compound.operator.as_str().to_string();
+                if compound.all {
+                    operation.push_str(" ALL");
+                }
+                let paging = paging(
+                    compound.order_by.iter().map(|o| o.to_string()).collect(),
+                    compound.limit,
+                    compound.offset,
+                );
+                let mut plan = Plan::new(operation + &paging)
+                    .child(left_plan)
+                    .child(right_plan);
+                let result = match (left, right) {
+                    (Some(left), Some(right)) => {
+                        let start = Instant::now();
+                        let result = left.combine(compound, right)?.sort_and_page(
+                            &compound.order_by,
+                            compound.limit,
+                            compound.offset,
+                        )?;
+                        plan.actual = Some((result.records.len(), start.elapsed()));
+                        Some(result)
+                    }
+                    _ => None,
+                };
+                Ok((plan, result))
+            }
+            Query::With(with) => {
+                let with_start = Instant::now();
+                let mut plan = Plan::new(match with.recursive {
+                    true => "WITH RECURSIVE".to_string(),
+                    false => "WITH".to_string(),
+                });
+                let mut scope = scope.clone();
+                for cte in &with.ctes {
+                    let start = Instant::now();
+                    let (mut cte_plan, result) = match recursive_union(with, cte) {
+                        // The recursive part runs once per round, so only the CTE as a
+                        // whole is measured.
+                        Some(compound) => {
+                            let result = match self.analyze {
+                                true => Some(execute_recursive(database, &scope, cte, compound)?),
+                                false => None,
+                            };
+                            let mut rounds = scope.clone();
+                            rounds.insert(cte.name.to_string(), no_rows());
+                            let describe = Explainer { analyze: false };
+                            let (anchor, _) = describe.query(database, &scope, &compound.left)?;
+                            let (step, _) = describe.query(database, &rounds, &compound.right)?;
+                            let plan = Plan::new(format!("Recursive CTE {}", cte.name))
+                                .child(anchor)
+                                .child(step);
+                            (plan, result)
+                        }
+                        None => {
+                            let (plan, result) = self.query(database, &scope, &cte.query)?;
+                            let plan = Plan::new(format!("CTE {}", cte.name)).child(plan);
+                            (plan, result.map(|r| r.named(cte)).transpose()?)
+                        }
+                    };
+                    let rows = match result {
+                        Some(result) => {
+                            cte_plan.actual = Some((result.records.len(), start.elapsed()));
+                            Rc::new(result)
+                        }
+                        None => no_rows(),
+                    };
+                    scope.insert(cte.name.to_string(), rows);
+                    plan.children.push(cte_plan);
+                }
+                let (body, result) = self.query(database, &scope, &with.body)?;
+                plan.actual = result
+                    .as_ref()
+                    .map(|r| (r.records.len(), with_start.elapsed()));
+                Ok((plan.child(body), result))
+            }
+        }
+    }
+
+    fn select<K: DatabaseKey>(
+        self,
+        database: &mut Database<K>,
+        scope: &Scope,
+        select: &SelectStatement,
+    ) -> Result<(Plan, Option<ResultSet>), CustomError> {
+        let mut select = select.clone();
+        let mut subqueries = Vec::new();
+        select.for_each_subquery(&mut |subquery| {
+            let (plan, result) = self.query(database, scope, &subquery.query)?;
+            subquery.result = result.map(|r| r.into_subquery_result());
+            subqueries.push(Plan::new("Subquery".to_string()).child(plan));
+            Ok(())
+        })?;
+
+        let mut stages = Stages::new();
+        let mut key_ordered = false;
+        let (source, result) = if !select.joins.is_empty() {
+            let source = self.joins(database, scope, &select)?;
+            let result = match self.analyze {
+                true => Some(select_joined(
+                    database,
+                    scope,
+                    &select,
+                    &mut |stage, rows| stages.trace(stage, rows),
+                )?),
+                false => None,
+            };
+            (source, result)
+        } else if let Some(derived) = &select.derived {
+            let (plan, rows) = self.query(database, scope, derived)?;
+            let source = Plan::new(format!("Subquery scan on {}", select.table_name)).child(plan);
+            (
+                source,
+                self.select_rows(&select, rows.map(Rc::new), &mut stages)?,
+            )
+        } else if let Some(rows) = scope.get(&select.table_name) {
+            let source = Plan::new(format!("CTE scan on {}", select.table_name));
+            let rows = self.analyze.then(|| rows.clone());
+            (source, self.select_rows(&select, rows, &mut stages)?)
+        } else {
+            let table = database.get_table(&select.table_name)?;
+            key_ordered = table
+                .key_order(&scope_to(&select, &table.columns)?)
+                .is_some();
+            let source = Plan::new(describe_scan(table, &select)?);
+            let result = match self.analyze {
+                true => Some(
+                    table.select_records(&select, &mut |stage, rows| stages.trace(stage, rows))?,
+                ),
+                false => None,
+            };
+            (source, result)
+        };
+
+        let mut plan = pipeline(&select, source, key_ordered, &stages.actual);
+        plan.children.extend(subqueries);
+        Ok((plan, result))
+    }
+
+    // A CTE or derived table: the rows are already there, `select_rows` does the rest.
+    fn select_rows(
+        self,
+        select: &SelectStatement,
+        rows: Option<Rc<ResultSet>>,
+        stages: &mut Stages,
+    ) -> Result<Option<ResultSet>, CustomError> {
+        let Some(rows) = rows else {
+            return Ok(None);
+        };
+        stages.trace(Stage::Source, rows.records.len());
+        let statement = scope_to(select, &rows.columns)?;
+        let result = select_rows(
+            rows.records.iter().collect(),
+            &rows.schema(),
+            &statement,
+            &mut |stage, count| stages.trace(stage, count),
+        )?;
+        Ok(Some(result))
+    }
+
+    // Each join reads its table by key when the ON clause names the key, and otherwise
+    // scans it once into a hash table.
+    fn joins<K: DatabaseKey>(
+        self,
+        database: &mut Database<K>,
+        scope: &Scope,
+        select: &SelectStatement,
+    ) -> Result<Plan, CustomError> {
+        let source = |database: &mut Database<K>, name: &str| match scope.get(name) {
+            Some(_) => Ok(Plan::new(format!("CTE scan on {}", name))),
+            None => database
+                .get_table(name)
+                .map(|_| Plan::new(format!("Full scan on {}", name))),
+        };
+        let mut plan = match &select.derived {
+            Some(derived) => {
+                let (plan, _) = Explainer { analyze: false }.query(database, scope, derived)?;
+                Plan::new(format!("Subquery scan on {}", select.table_name)).child(plan)
+            }
+            None => source(database, &select.table_name)?,
+        };
+        for join in &select.joins {
+            let key = match scope.get(&join.table_name) {
+                Some(_) => None,
+                None => Some(&database.get_table(&join.table_name)?.key_field),
+            };
+            plan = match key.filter(|key| on_key(join, key)) {
+                Some(key) => {
+                    Plan::new(format!("{}, by key {}.{}", join, join.table_name, key)).child(plan)
+                }
+                None => Plan::new(format!("{}, by hash", join))
+                    .child(plan)
+                    .child(source(database, &join.table_name)?),
+            };
+        }
+        Ok(plan)
+    }
+}
+
+// Whether one side of the ON clause is `key` of the joined table.
+fn on_key(join: &Join, key: &str) -> bool {
+    let qualified = format!("{}.{}", join.table_name, key);
+    let other_table = |side: &str| side.contains('.') && !side.starts_with(&join.table_name);
+    [(&join.left, &join.right), (&join.right, &join.left)]
+        .iter()
+        .any(|(side, other)| **side == qualified || (**side == key && other_table(other)))
+}
+
+// The stages `select_rows` runs over the rows of `source`, innermost first.
+fn pipeline(
+    select: &SelectStatement,
+    source: Plan,
+    key_ordered: bool,
+    actual: &HashMap<Stage, (usize, Duration)>,
+) -> Plan {
+    let mut plan = source;
+    plan.actual = actual.get(&Stage::Source).copied();
+    let stage = |plan: Plan, stage: Stage, operation: String| Plan {
+        actual: actual.get(&stage).copied(),
+        ..Plan::new(operation).child(plan)
+    };
+
+    if let Some(condition) = &select.condition {
+        plan = stage(plan, Stage::Filter, format!("Filter: {}", condition));
+    }
+    if select.is_grouped() {
+        let mut parts = Vec::new();
+        if !select.group_by.is_empty() {
+            parts.push(format!("GROUP BY {}", select.group_by.join(", ")));
+        }
+        if let Some(having) = &select.having {
+            parts.push(format!("HAVING {}", having));
+        }
+        let operation = match parts.is_empty() {
+            true => "Aggregate".to_string(),
+            false => format!("Aggregate: {}", parts.join(" ")),
+        };
+        plan = stage(plan, Stage::Aggregate, operation);
+    }
+    let windows = select.windows();
+    if !windows.is_empty() {
+        let windows = windows.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        plan = stage(
+            plan,
+            Stage::Window,
+            format!("Window: {}", windows.join(", ")),
+        );
+    }
+    if !select.order_by.is_empty() && !key_ordered {
+        let keys = select
+            .order_by
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>();
+        plan = stage(plan, Stage::Sort, format!("Sort: {}", keys.join(", ")));
+    }
+
+    let projections = select
+        .fields
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>();
+    let mut flags = Vec::new();
+    if select.distinct {
+        flags.push("DISTINCT".to_string());
+    }
+    let operation = format!("Project: {}", projections.join(", "))
+        + &paging(flags, select.limit, select.offset);
+    stage(plan, Stage::Output, operation)
+}
+
+// ` (ORDER BY ..., LIMIT n, OFFSET m)`, or nothing when no part applies.
+fn paging(mut parts: Vec<String>, limit: Option<usize>, offset: usize) -> String {
+    if let Some(limit) = limit {
+        parts.push(format!("LIMIT {}", limit));
+    }
+    if offset > 0 {
+        parts.push(format!("OFFSET {}", offset));
+    }
+    match parts.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", parts.join(", ")),
+    }
+}
+
+// How `Table::select_records` reads the table: every record, a key range, one key, or
+// nothing at all, and whether it can stop early because the rows are in key order.
+fn describe_scan<K: DatabaseKey>(
+    table: &Table<K>,
+    select: &SelectStatement,
+) -> Result<String, CustomError> {
+    let statement = scope_to(select, &table.columns)?;
+    // Subqueries that have not run cannot be bound; leaving their conditions out
+    // only widens the range.
+    let condition = statement.condition.as_ref().and_then(without_pending);
+    let predicate = bind_condition(&condition, &table.fields)?;
+    let key = &table.key_field;
+    let mut operation = match table.key_range(predicate.as_ref()) {
+        None => {
+            return Ok(format!(
+                "Empty scan on {}: no key can match",
+                table.table_name
+            ));
+        }
+        Some((Bound::Unbounded, Bound::Unbounded)) => format!("Full scan on {}", table.table_name),
+        Some((Bound::Included(low), Bound::Included(high))) if low == high => {
+            format!(
+                "Key lookup on {}: {} = {}",
+                table.table_name,
+                key,
+                low.to_string_2()
+            )
+        }
+        Some((low, high)) => {
+            let mut bounds = Vec::new();
+            match low {
+                Bound::Included(v) => bounds.push(format!("{} >= {}", key, v.to_string_2())),
+                Bound::Excluded(v) => bounds.push(format!("{} > {}", key, v.to_string_2())),
+                Bound::Unbounded => {}
+            }
+            match high {
+                Bound::Included(v) => bounds.push(format!("{} <= {}", key, v.to_string_2())),
+                Bound::Excluded(v) => bounds.push(format!("{} < {}", key, v.to_string_2())),
+                Bound::Unbounded => {}
+            }
+            format!(
+                "Key range scan on {}: {}",
+                table.table_name,
+                bounds.join(" AND ")
+            )
+        }
+    };
+    let key_order = table.key_order(&statement);
+    match key_order {
+        Some(true) => operation.push_str(", in descending key order"),
+        Some(false) if !statement.order_by.is_empty() => operation.push_str(", in key order"),
+        _ => {}
+    }
+    if key_order.is_some() && statement.limit.is_some() {
+        operation.push_str(", stops at the LIMIT");
+    }
+    Ok(operation)
+}
+
+// Plain EXPLAIN runs no CTE, so the names are in scope without rows.
+fn no_rows() -> Rc<ResultSet> {
+    Rc::new(ResultSet {
+        columns: Vec::new(),
+        types: Vec::new(),
+        records: Vec::new(),
+    })
+}
+
+fn without_pending(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::And(l, r) => match (without_pending(l), without_pending(r)) {
+            (Some(l), Some(r)) => Some(Expr::And(Box::new(l), Box::new(r))),
+            (l, r) => l.or(r),
+        },
+        _ => {
+            let mut subqueries = Vec::new();
+            expr.subqueries(&mut subqueries);
+            let pending = subqueries.iter().any(|s| s.result.is_none());
+            (!pending).then(|| expr.clone())
+        }
+    }
+}
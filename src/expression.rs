@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        ArithmeticOp, Case, CaseWhen, Column, Literal, Operand, ScalarFunction, Subquery,
+        SubqueryResult,
+    },
+    condition::{Op, Predicate},
+    custom_error::CustomError,
+    database::{Record, Value},
+    parsers::parse_predicate,
+};
+
+// An operand bound against a schema: fields are known to exist and every node has a type.
+pub enum Scalar {
+    Field(String),
+    Constant(Value),
+    Null,
+    Negate(Box<Scalar>),
+    Arithmetic(ArithmeticOp, Box<Scalar>, Box<Scalar>),
+    Function(ScalarFunction, Vec<Scalar>),
+    ToFloat(Box<Scalar>),
+    Case {
+        subject: Option<Box<Scalar>>,
+        branches: Vec<(BoundWhen, Scalar)>,
+        otherwise: Option<Box<Scalar>>,
+    },
+}
+
+pub enum BoundWhen {
+    Value(Scalar),
+    Condition(Predicate),
+}
+
+pub fn canonical_type(type_name: &str) -> String {
+    match type_name.trim().to_ascii_uppercase().as_str() {
+        "INT" => "Int".to_string(),
+        "FLOAT" => "Float".to_string(),
+        "STRING" => "String".to_string(),
+        "BOOL" => "Bool".to_string(),
+        _ => type_name.to_string(),
+    }
+}
+
+pub fn is_numeric(type_name: &str) -> bool {
+    matches!(type_name, "Int" | "Float")
+}
+
+pub fn bind_operand(
+    operand: &Operand,
+    fields: &HashMap<String, String>,
+) -> Result<(Scalar, String), CustomError> {
+    bind_typed(operand, fields, None)
+}
+
+// `hint` is the type a literal takes when the other side of a comparison decides it,
+// so that `city = 10` compares with the string "10".
+pub fn bind_typed(
+    operand: &Operand,
+    fields: &HashMap<String, String>,
+    hint: Option<&str>,
+) -> Result<(Scalar, String), CustomError> {
+    match operand {
+        Operand::Column(column) => {
+            let name = match column {
+                Column::Field(name) => name.to_string(),
+                Column::Aggregate(a) => a.to_string(),
+            };
+            let field_type = fields
+                .get(&name)
+                .ok_or_else(|| CustomError::UnknownField(name.to_string()))?;
+            Ok((Scalar::Field(name), canonical_type(field_type)))
+        }
+        Operand::Window(w) => {
+            let name = w.to_string();
+            let field_type = fields.get(&name).ok_or_else(|| {
+                CustomError::WindowError(format!(
+                    "{} can only be used in the select list or ORDER BY",
+                    name
+                ))
+            })?;
+            Ok((Scalar::Field(name), canonical_type(field_type)))
+        }
+        Operand::Literal(literal) => bind_literal(literal, hint),
+        // NULL has the type its place expects; on its own it fits next to any type.
+        Operand::Null => Ok((Scalar::Null, hint.unwrap_or("Null").to_string())),
+        Operand::Negate(inner) => {
+            let (scalar, typ) = bind_typed(inner, fields, hint)?;
+            expect_numeric(&typ, || format!("cannot negate {} of type {}", inner, typ))?;
+            Ok((Scalar::Negate(Box::new(scalar)), typ))
+        }
+        Operand::Arithmetic { op, left, right } => {
+            let (left_scalar, left_type) = bind_typed(left, fields, None)?;
+            let (right_scalar, right_type) = bind_typed(right, fields, None)?;
+            let (left_type, right_type) = match (left_type.as_str(), right_type.as_str()) {
+                ("Null", "Null") => ("Int".to_string(), "Int".to_string()),
+                ("Null", _) => (right_type.clone(), right_type),
+                (_, "Null") => (left_type.clone(), left_type),
+                _ => (left_type, right_type),
+            };
+            for (side, typ) in [(left, &left_type), (right, &right_type)] {
+                expect_numeric(typ, || {
+                    format!("{} needs numbers, {} is {}", op.symbol(), side, typ)
+                })?;
+            }
+            let typ = if left_type == "Int" && right_type == "Int" {
+                "Int"
+            } else {
+                "Float"
+            };
+            Ok((
+                Scalar::Arithmetic(*op, Box::new(left_scalar), Box::new(right_scalar)),
+                typ.to_string(),
+            ))
+        }
+        Operand::Function { function, args } => bind_function(*function, args, fields, hint),
+        Operand::Case(case) => bind_case(case, fields, hint),
+        Operand::Subquery(subquery) => {
+            let (column, typ) = subquery_column(subquery)?;
+            match column.as_slice() {
+                [] | [None] => Ok((Scalar::Null, typ)),
+                [Some(value)] => Ok((Scalar::Constant(value.clone()), typ)),
+                rows => Err(CustomError::SubqueryError(format!(
+                    "{} returned {} rows where a single value is expected",
+                    operand,
+                    rows.len()
+                ))),
+            }
+        }
+    }
+}
+
+pub fn subquery_result(subquery: &Subquery) -> Result<&SubqueryResult, CustomError> {
+    subquery.result.as_ref().ok_or_else(|| {
+        CustomError::SubqueryError(format!("({}) was not evaluated", subquery.query))
+    })
+}
+
+// The values of a subquery used where a value or a list of values is expected.
+pub fn subquery_column(subquery: &Subquery) -> Result<(Vec<Option<Value>>, String), CustomError> {
+    let result = subquery_result(subquery)?;
+    match result.types.as_slice() {
+        [typ] => Ok((
+            result.rows.iter().map(|row| row[0].clone()).collect(),
+            canonical_type(typ),
+        )),
+        types => Err(CustomError::SubqueryError(format!(
+            "({}) must return one column, found {}",
+            subquery.query,
+            types.len()
+        ))),
+    }
+}
+
+fn bind_literal(literal: &Literal, hint: Option<&str>) -> Result<(Scalar, String), CustomError> {
+    let text = literal.text();
+    let inferred = match literal {
+        Literal::Number(n) if n.contains('.') => "Float",
+        Literal::Number(_) => "Int",
+        Literal::Word(w) if w.eq_ignore_ascii_case("true") || w.eq_ignore_ascii_case("false") => {
+            "Bool"
+        }
+        Literal::Word(_) | Literal::Quoted(_) => "String",
+        Literal::Parameter(n) => {
+            return Err(CustomError::ParameterError(format!("${} has no value", n)));
+        }
+    };
+    // A number keeps its own type next to a numeric field, so `pages > 2.5` works, and
+    // quoted text stays a String, so `pages > '2'` is a type error.
+    let typ = match hint {
+        Some(_) if matches!(literal, Literal::Quoted(_)) => inferred,
+        Some(hint) if !(is_numeric(hint) && is_numeric(inferred)) => hint,
+        _ => inferred,
+    };
+    let value = Value::from_literal(literal, typ).ok_or_else(|| {
+        CustomError::ConditionParseError(format!("Cannot parse value '{}' as type '{}'", text, typ))
+    })?;
+    Ok((Scalar::Constant(value), typ.to_string()))
+}
+
+fn bind_function(
+    function: ScalarFunction,
+    args: &[Operand],
+    fields: &HashMap<String, String>,
+    hint: Option<&str>,
+) -> Result<(Scalar, String), CustomError> {
+    let arity = match function {
+        ScalarFunction::Round => 1..=2,
+        ScalarFunction::Coalesce => 1..=usize::MAX,
+        _ => 1..=1,
+    };
+    if !arity.contains(&args.len()) {
+        return Err(CustomError::TypeError(format!(
+            "{} does not take {} arguments",
+            function.as_str(),
+            args.len()
+        )));
+    }
+
+    let mut scalars = Vec::new();
+    let mut types = Vec::new();
+    for arg in args {
+        let hint = match function {
+            ScalarFunction::Coalesce => hint,
+            _ => None,
+        };
+        let (scalar, typ) = bind_typed(arg, fields, hint)?;
+        scalars.push(scalar);
+        types.push(typ);
+    }
+    let argument_error = |index: usize, expected: &str| {
+        CustomError::TypeError(format!(
+            "{} expects {}, {} is {}",
+            function.as_str(),
+            expected,
+            args[index],
+            types[index]
+        ))
+    };
+
+    let typ = match function {
+        ScalarFunction::Upper | ScalarFunction::Lower | ScalarFunction::Length => {
+            if types[0] != "String" {
+                return Err(argument_error(0, "a String"));
+            }
+            match function {
+                ScalarFunction::Length => "Int".to_string(),
+                _ => "String".to_string(),
+            }
+        }
+        ScalarFunction::Abs | ScalarFunction::Round => {
+            if !is_numeric(&types[0]) {
+                return Err(argument_error(0, "a number"));
+            }
+            if types.get(1).is_some_and(|t| t != "Int") {
+                return Err(argument_error(1, "an Int number of digits"));
+            }
+            types[0].to_string()
+        }
+        ScalarFunction::Coalesce => {
+            let (unified, typ) = unify(scalars, &types)
+                .map_err(|i| argument_error(i, &format!("arguments of type {}", types[0])))?;
+            return Ok((Scalar::Function(function, unified), typ));
+        }
+    };
+    Ok((Scalar::Function(function, scalars), typ))
+}
+
+fn bind_case(
+    case: &Case,
+    fields: &HashMap<String, String>,
+    hint: Option<&str>,
+) -> Result<(Scalar, String), CustomError> {
+    let subject = case
+        .subject
+        .as_ref()
+        .map(|s| bind_operand(s, fields))
+        .transpose()?;
+
+    let mut whens = Vec::new();
+    let mut results = Vec::new();
+    let mut types = Vec::new();
+    let outcomes = case
+        .branches
+        .iter()
+        .map(|(when, then)| (Some(when), then))
+        .chain(case.otherwise.iter().map(|o| (None, o)));
+    for (when, then) in outcomes {
+        match (when, &subject) {
+            (Some(CaseWhen::Value(value)), Some((_, subject_type))) => {
+                let (scalar, typ) = bind_typed(value, fields, Some(subject_type))?;
+                if !comparable(&typ, subject_type) {
+                    return Err(CustomError::TypeError(format!(
+                        "CASE cannot compare {} ({}) with {} ({})",
+                        case.subject
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                        subject_type,
+                        value,
+                        typ
+                    )));
+                }
+                whens.push(BoundWhen::Value(scalar));
+            }
+            (Some(CaseWhen::Condition(expr)), _) => {
+                whens.push(BoundWhen::Condition(parse_predicate(expr, fields)?));
+            }
+            (Some(CaseWhen::Value(value)), None) => {
+                return Err(CustomError::TypeError(format!(
+                    "CASE WHEN {} needs a CASE subject to compare with",
+                    value
+                )));
+            }
+            (None, _) => {}
+        }
+        let (scalar, typ) = bind_typed(then, fields, hint)?;
+        results.push(scalar);
+        types.push(typ);
+    }
+
+    let (mut results, typ) = unify(results, &types).map_err(|i| {
+        let outcome = match case.branches.get(i) {
+            Some((_, then)) => then.to_string(),
+            None => case
+                .otherwise
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        };
+        CustomError::TypeError(format!(
+            "CASE branches must have one type, {} is {} but {} is {}",
+            case.branches[0].1, types[0], outcome, types[i]
+        ))
+    })?;
+    let otherwise = match case.otherwise {
+        Some(_) => results.pop().map(Box::new),
+        None => None,
+    };
+    Ok((
+        Scalar::Case {
+            subject: subject.map(|(s, _)| Box::new(s)),
+            branches: whens.into_iter().zip(results).collect(),
+            otherwise,
+        },
+        typ,
+    ))
+}
+
+// CASE branches and COALESCE arguments must agree; a mix of Int and Float becomes Float
+// and NULL fits any type. On a mismatch, returns the index of the first scalar that
+// does not fit.
+fn unify(scalars: Vec<Scalar>, types: &[String]) -> Result<(Vec<Scalar>, String), usize> {
+    let Some(first) = types.iter().find(|t| *t != "Null") else {
+        return Ok((scalars, types[0].to_string()));
+    };
+    if types.iter().all(|t| t == first || t == "Null") {
+        return Ok((scalars, first.to_string()));
+    }
+    if let Some(index) = types.iter().position(|t| !comparable(t, first)) {
+        return Err(index);
+    }
+    let scalars = scalars
+        .into_iter()
+        .map(|s| Scalar::ToFloat(Box::new(s)))
+        .collect();
+    Ok((scalars, "Float".to_string()))
+}
+
+// Int and Float can be compared with each other, NULL with anything, and any other types
+// only with themselves.
+pub fn comparable(a: &str, b: &str) -> bool {
+    a == b || (is_numeric(a) && is_numeric(b)) || a == "Null" || b == "Null"
+}
+
+fn expect_numeric(typ: &str, message: impl Fn() -> String) -> Result<(), CustomError> {
+    match is_numeric(typ) {
+        true => Ok(()),
+        false => Err(CustomError::TypeError(message())),
+    }
+}
+
+impl Scalar {
+    // None is NULL: a missing field, or an overflow or division by zero along the way.
+    pub fn evaluate(&self, record: &Record) -> Option<Value> {
+        match self {
+            Scalar::Field(name) => record.values.get(name).cloned(),
+            Scalar::Constant(value) => Some(value.clone()),
+            Scalar::Null => None,
+            Scalar::Negate(inner) => match inner.evaluate(record)? {
+                Value::Int(i) => i.checked_neg().map(Value::Int),
+                Value::Float(f) => Some(Value::Float(-f)),
+                _ => None,
+            },
+            Scalar::Arithmetic(op, left, right) => {
+                arithmetic(*op, left.evaluate(record)?, right.evaluate(record)?)
+            }
+            Scalar::ToFloat(inner) => inner.evaluate(record)?.as_f64().map(Value::Float),
+            Scalar::Function(ScalarFunction::Coalesce, args) => {
+                args.iter().find_map(|a| a.evaluate(record))
+            }
+            Scalar::Function(function, args) => {
+                let values = args
+                    .iter()
+                    .map(|a| a.evaluate(record))
+                    .collect::<Option<Vec<_>>>()?;
+                call(*function, &values)
+            }
+            Scalar::Case {
+                subject,
+                branches,
+                otherwise,
+            } => {
+                let subject = subject.as_ref().map(|s| s.evaluate(record));
+                let chosen = branches.iter().find(|(when, _)| match (when, &subject) {
+                    (BoundWhen::Condition(predicate), _) => predicate.matches(record),
+                    (BoundWhen::Value(value), Some(Some(subject))) => value
+                        .evaluate(record)
+                        .is_some_and(|v| Op::Eq.cmp(subject, &v)),
+                    (BoundWhen::Value(_), _) => false,
+                });
+                match chosen {
+                    Some((_, then)) => then.evaluate(record),
+                    None => otherwise.as_ref()?.evaluate(record),
+                }
+            }
+        }
+    }
+}
+
+fn arithmetic(op: ArithmeticOp, left: Value, right: Value) -> Option<Value> {
+    if let (Value::Int(a), Value::Int(b)) = (&left, &right) {
+        let result = match op {
+            ArithmeticOp::Add => a.checked_add(*b),
+            ArithmeticOp::Subtract => a.checked_sub(*b),
+            ArithmeticOp::Multiply => a.checked_mul(*b),
+            ArithmeticOp::Divide => a.checked_div(*b),
+        };
+        return result.map(Value::Int);
+    }
+    let (a, b) = (left.as_f64()?, right.as_f64()?);
+    let result = match op {
+        ArithmeticOp::Add => a + b,
+        ArithmeticOp::Subtract => a - b,
+        ArithmeticOp::Multiply => a * b,
+        ArithmeticOp::Divide if b == 0.0 => return None,
+        ArithmeticOp::Divide => a / b,
+    };
+    Some(Value::Float(result))
+}
+
+fn call(function: ScalarFunction, args: &[Value]) -> Option<Value> {
+    match (function, args) {
+        (ScalarFunction::Upper, [Value::String(s)]) => Some(Value::String(s.to_uppercase())),
+        (ScalarFunction::Lower, [Value::String(s)]) => Some(Value::String(s.to_lowercase())),
+        (ScalarFunction::Length, [Value::String(s)]) => Some(Value::Int(s.chars().count() as i64)),
+        (ScalarFunction::Abs, [Value::Int(i)]) => i.checked_abs().map(Value::Int),
+        (ScalarFunction::Abs, [Value::Float(f)]) => Some(Value::Float(f.abs())),
+        (ScalarFunction::Round, [Value::Int(i), ..]) => Some(Value::Int(*i)),
+        (ScalarFunction::Round, [Value::Float(f)]) => Some(Value::Float(f.round())),
+        (ScalarFunction::Round, [Value::Float(f), Value::Int(digits)]) => {
+            let scale = 10f64.powi((*digits).clamp(-308, 308) as i32);
+            Some(Value::Float((f * scale).round() / scale))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        ast::{ArithmeticOp, Case, CaseWhen, Literal, Operand, ScalarFunction},
+        custom_error::CustomError,
+        database::{Record, Value},
+    };
+
+    use super::bind_operand;
+
+    fn number(n: &str) -> Operand {
+        Operand::Literal(Literal::Number(n.to_string()))
+    }
+
+    #[test]
+    fn value_branch_without_subject_is_an_error() {
+        let case = Operand::Case(Box::new(Case {
+            subject: None,
+            branches: vec![(CaseWhen::Value(number("1")), number("2"))],
+            otherwise: None,
+        }));
+        let result = bind_operand(&case, &HashMap::new());
+        assert!(matches!(result, Err(CustomError::TypeError(_))));
+    }
+
+    #[test]
+    fn null_takes_the_type_of_the_other_arguments() {
+        let coalesce = Operand::Function {
+            function: ScalarFunction::Coalesce,
+            args: vec![Operand::Null, number("2")],
+        };
+        let (scalar, typ) = bind_operand(&coalesce, &HashMap::new()).unwrap();
+        assert_eq!(typ, "Int");
+        assert_eq!(scalar.evaluate(&Record::new()), Some(Value::Int(2)));
+
+        let sum = Operand::Arithmetic {
+            op: ArithmeticOp::Add,
+            left: Box::new(number("1.5")),
+            right: Box::new(Operand::Null),
+        };
+        let (scalar, typ) = bind_operand(&sum, &HashMap::new()).unwrap();
+        assert_eq!(typ, "Float");
+        assert_eq!(scalar.evaluate(&Record::new()), None);
+    }
+
+    #[test]
+    fn case_branches_unify_to_float() {
+        let case = Operand::Case(Box::new(Case {
+            subject: Some(number("1")),
+            branches: vec![(CaseWhen::Value(number("1")), number("2"))],
+            otherwise: Some(number("2.5")),
+        }));
+        let (_, typ) = bind_operand(&case, &HashMap::new()).unwrap();
+        assert_eq!(typ, "Float");
+    }
+}
@@ -1,8 +1,17 @@
 use crate::{
+    ast::{
+        AlterStatement, CreateStatement, DeleteStatement, DropStatement, InsertStatement, Literal,
+        Query, Statement, TruncateStatement, UpdateStatement,
+    },
     command_history::CommandHistory,
-    commands::{Command, Create, Delete, Insert, ReadFrom, SaveAs, Select, Serialize},
+    commands::{
+        Alter, Command, Create, Delete, Drop, Explain, Insert, ReadFrom, SaveAs, Select, Serialize,
+        Truncate, Update,
+    },
     custom_error::CustomError,
     database::{AnyDatabase, Database, DatabaseKey},
+    parser::parse,
+    prepared::PreparedStatement,
 };
 
 pub fn handle_input_any_db(
@@ -20,68 +29,172 @@ pub fn handle_input(
     database: &mut Database<impl DatabaseKey>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let trimmed = input.trim();
+    handle_statement(parse(input.trim())?, database, history)
+}
 
-    match true {
-        _ if trimmed.starts_with("CREATE") => handle_create(trimmed, database, history),
-        _ if trimmed.starts_with("INSERT") => handle_insert(trimmed, database, history),
-        _ if trimmed.starts_with("DELETE") => handle_delete(trimmed, database, history),
-        _ if trimmed.starts_with("SELECT") => handle_select(trimmed, database, history),
-        _ if trimmed.starts_with("SAVE_AS") => handle_save_as(trimmed, history),
-        _ if trimmed.starts_with("READ_FROM") => handle_read_from(trimmed, database, history),
-        _ => Err(CustomError::UnknownCommand(trimmed.to_string())),
+// Runs a parsed statement, such as one returned by `PreparedStatement::bind`.
+pub fn handle_statement(
+    mut statement: Statement,
+    database: &mut Database<impl DatabaseKey>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    statement.for_each_literal(&mut |literal| match literal {
+        Literal::Parameter(n) => Err(CustomError::ParameterError(format!(
+            "${} has no value, a statement with parameters has to be prepared",
+            n
+        ))),
+        _ => Ok(()),
+    })?;
+    match statement {
+        Statement::Create(statement) => handle_create(statement, database, history),
+        Statement::Insert(statement) => handle_insert(statement, database, history),
+        Statement::Delete(statement) => handle_delete(statement, database, history),
+        Statement::Select(statement) => handle_select(statement, database, history),
+        Statement::Update(statement) => handle_update(statement, database, history),
+        Statement::Drop(statement) => handle_drop(statement, database, history),
+        Statement::Truncate(statement) => handle_truncate(statement, database, history),
+        Statement::Alter(statement) => handle_alter(statement, database, history),
+        Statement::SaveAs(file_path) => handle_save_as(file_path, history),
+        Statement::ReadFrom(file_path) => handle_read_from(file_path, database, history),
+        Statement::Explain { analyze, query } => handle_explain(query, analyze, database, history),
+        Statement::Prepare { name, statement } => handle_prepare(name, *statement, database),
+        Statement::Execute { name, arguments } => {
+            handle_execute(name, arguments, database, history)
+        }
     }
 }
 
 pub fn handle_create<K: DatabaseKey>(
-    input: &str,
+    statement: CreateStatement,
     database: &mut Database<K>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let mut command = Create::new(database, input)?;
+    let mut command = Create::new(database, statement)?;
+    let result = command.execute()?;
     history.push(command.serialize());
-    command.execute()
+    Ok(result)
 }
 pub fn handle_insert<K: DatabaseKey>(
-    input: &str,
+    statement: InsertStatement,
     database: &mut Database<K>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let mut command = Insert::new(database, input)?;
+    let mut command = Insert::new(database, statement)?;
+    let result = command.execute()?;
     history.push(command.serialize());
-    command.execute()
+    Ok(result)
 }
 pub fn handle_delete<K: DatabaseKey>(
-    input: &str,
+    statement: DeleteStatement,
     database: &mut Database<K>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let mut command = Delete::new(database, input)?;
+    let mut command = Delete::new(database, statement)?;
+    let result = command.execute()?;
     history.push(command.serialize());
-    command.execute()
+    Ok(result)
 }
 pub fn handle_select<K: DatabaseKey>(
-    input: &str,
+    query: Query,
     database: &mut Database<K>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let mut command = Select::new(database, input)?;
+    let mut command = Select::new(database, query)?;
+    let result = command.execute()?;
     history.push(command.serialize());
-
-    command.execute()
+    Ok(result)
 }
-pub fn handle_save_as(input: &str, history: &mut CommandHistory) -> Result<String, CustomError> {
-    let mut command = SaveAs::new(input, history)?;
+pub fn handle_explain<K: DatabaseKey>(
+    query: Query,
+    analyze: bool,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = Explain::new(database, query, analyze);
+    let result = command.execute()?;
+    history.push(command.serialize());
+    Ok(result)
+}
+pub fn handle_update<K: DatabaseKey>(
+    statement: UpdateStatement,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = Update::new(database, statement)?;
+    let result = command.execute()?;
+    history.push(command.serialize());
+    Ok(result)
+}
+pub fn handle_drop<K: DatabaseKey>(
+    statement: DropStatement,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = Drop::new(database, statement);
+    let result = command.execute()?;
+    history.push(command.serialize());
+    Ok(result)
+}
+pub fn handle_truncate<K: DatabaseKey>(
+    statement: TruncateStatement,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = Truncate::new(database, statement)?;
+    let result = command.execute()?;
+    history.push(command.serialize());
+    Ok(result)
+}
+pub fn handle_alter<K: DatabaseKey>(
+    statement: AlterStatement,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = Alter::new(database, statement)?;
+    let result = command.execute()?;
+    history.push(command.serialize());
+    Ok(result)
+}
+pub fn handle_save_as(
+    file_path: String,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let mut command = SaveAs::new(file_path, history);
 
     command.execute()
 }
 
 pub fn handle_read_from<K: DatabaseKey>(
-    input: &str,
+    file_path: String,
     database: &mut Database<K>,
     history: &mut CommandHistory,
 ) -> Result<String, CustomError> {
-    let mut command = ReadFrom::new(input, history, database)?;
+    let mut command = ReadFrom::new(file_path, history, database);
 
     command.execute()
 }
+
+// PREPARE is not kept in the history: each EXECUTE is recorded as the statement it ran.
+pub fn handle_prepare<K: DatabaseKey>(
+    name: String,
+    statement: Statement,
+    database: &mut Database<K>,
+) -> Result<String, CustomError> {
+    let prepared = PreparedStatement::new(database, statement)?;
+    database.prepared.insert(name, prepared);
+    Ok("Statement prepared succesfully".to_string())
+}
+
+pub fn handle_execute<K: DatabaseKey>(
+    name: String,
+    arguments: Vec<Literal>,
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+) -> Result<String, CustomError> {
+    let statement = database
+        .prepared
+        .get(&name)
+        .ok_or_else(|| CustomError::ParameterError(format!("no statement prepared as {}", name)))?
+        .bind_literals(&arguments)?;
+    handle_statement(statement, database, history)
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Join, JoinKind, Projection, SelectStatement},
+    custom_error::CustomError,
+    database::{Database, DatabaseKey, Record, Table, Value},
+    expression::{canonical_type, comparable},
+    query::{ResultSet, Scope, Stage, execute_in, select_rows},
+};
+
+type Lookup<'t> = dyn Fn(&Value) -> Vec<&'t Record> + 't;
+
+// A table, or the rows of a derived table or CTE, under the name the query gives it.
+struct Source<'t> {
+    name: String,
+    columns: Vec<String>,
+    fields: HashMap<String, String>,
+    records: Vec<&'t Record>,
+    // Finds records by this field without a scan; only a table's key field has one.
+    key: Option<(String, Box<Lookup<'t>>)>,
+}
+
+impl<'t> Source<'t> {
+    fn table<K: DatabaseKey>(table: &'t Table<K>) -> Self {
+        let lookup = move |value: &Value| match K::from_value(value) {
+            Ok(key) => table.records.get(&key).into_iter().collect(),
+            Err(_) => Vec::new(),
+        };
+        Self {
+            name: table.table_name.to_string(),
+            columns: table.columns.clone(),
+            fields: table.fields.clone(),
+            records: table.records.values().collect(),
+            key: Some((table.key_field.to_string(), Box::new(lookup))),
+        }
+    }
+
+    fn rows(name: &str, result: &'t ResultSet) -> Self {
+        Self {
+            name: name.to_string(),
+            columns: result.columns.clone(),
+            fields: result.schema(),
+            records: result.records.iter().collect(),
+            key: None,
+        }
+    }
+
+    fn lookup(self, field: &str) -> Box<Lookup<'t>> {
+        match self.key {
+            Some((key_field, lookup)) if key_field == field => lookup,
+            _ => {
+                let mut index: HashMap<&Value, Vec<&Record>> = HashMap::new();
+                for record in self.records {
+                    if let Some(value) = record.values.get(field) {
+                        index.entry(value).or_default().push(record);
+                    }
+                }
+                Box::new(move |value: &Value| index.get(value).cloned().unwrap_or_default())
+            }
+        }
+    }
+}
+
+// Columns of a joined row set are always qualified as `table.field`.
+struct JoinedRows {
+    tables: Vec<String>,
+    columns: Vec<String>,
+    fields: HashMap<String, String>,
+    rows: Vec<Record>,
+}
+
+impl JoinedRows {
+    fn new(source: Source) -> Self {
+        let mut joined = Self {
+            tables: Vec::new(),
+            columns: Vec::new(),
+            fields: HashMap::new(),
+            rows: Vec::new(),
+        };
+        joined.add_table(&source.name, &source.columns, &source.fields);
+        joined.rows = source
+            .records
+            .iter()
+            .map(|r| qualify(&source.name, r))
+            .collect();
+        joined
+    }
+
+    fn add_table(
+        &mut self,
+        table_name: &str,
+        columns: &[String],
+        fields: &HashMap<String, String>,
+    ) {
+        self.tables.push(table_name.to_string());
+        for name in columns {
+            let qualified = format!("{}.{}", table_name, name);
+            self.fields
+                .insert(qualified.to_string(), fields[name].to_string());
+            self.columns.push(qualified);
+        }
+    }
+
+    fn expand(&self, table: Option<&str>) -> Result<Vec<String>, CustomError> {
+        match table {
+            None => Ok(self.columns.clone()),
+            Some(t) if self.tables.iter().any(|name| name == t) => {
+                let prefix = format!("{}.", t);
+                Ok(self
+                    .columns
+                    .iter()
+                    .filter(|c| c.starts_with(&prefix))
+                    .cloned()
+                    .collect())
+            }
+            Some(t) => Err(CustomError::TableNotFound(t.to_string())),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Result<String, CustomError> {
+        if name.contains('.') {
+            return match self.fields.contains_key(name) {
+                true => Ok(name.to_string()),
+                false => Err(CustomError::UnknownField(name.to_string())),
+            };
+        }
+        let mut candidates = self
+            .tables
+            .iter()
+            .map(|t| format!("{}.{}", t, name))
+            .filter(|q| self.fields.contains_key(q));
+        match (candidates.next(), candidates.next()) {
+            (Some(qualified), None) => Ok(qualified),
+            (Some(_), Some(_)) => Err(CustomError::AmbiguousField(name.to_string())),
+            (None, _) => Err(CustomError::UnknownField(name.to_string())),
+        }
+    }
+
+    fn join(&mut self, source: Source, join: &Join) -> Result<(), CustomError> {
+        if self.tables.contains(&source.name) {
+            return Err(CustomError::JoinError(format!(
+                "table {} appears more than once",
+                source.name
+            )));
+        }
+        self.add_table(&source.name, &source.columns, &source.fields);
+
+        let prefix = format!("{}.", source.name);
+        let left = self.resolve(&join.left)?;
+        let right = self.resolve(&join.right)?;
+        let (outer, inner) = match (left.starts_with(&prefix), right.starts_with(&prefix)) {
+            (false, true) => (left, right),
+            (true, false) => (right, left),
+            _ => {
+                return Err(CustomError::JoinError(format!(
+                    "ON must compare a column of {} with a column of a previous table",
+                    source.name
+                )));
+            }
+        };
+
+        let outer_type = canonical_type(&self.fields[&outer]);
+        let inner_type = canonical_type(&self.fields[&inner]);
+        if !comparable(&outer_type, &inner_type) {
+            return Err(CustomError::TypeError(format!(
+                "cannot join on {} ({}) = {} ({})",
+                outer, outer_type, inner, inner_type
+            )));
+        }
+
+        // Joining on a table's key field is a direct BTreeMap lookup; anything else
+        // builds a hash table over the joined rows once.
+        let name = source.name.to_string();
+        let lookup = source.lookup(&inner[prefix.len()..]);
+
+        let mut rows = Vec::new();
+        for row in std::mem::take(&mut self.rows) {
+            let value = row.values.get(&outer).and_then(|v| as_type(v, &inner_type));
+            let matches = match value {
+                Some(value) => lookup(&value),
+                None => Vec::new(),
+            };
+            if matches.is_empty() && join.kind == JoinKind::Left {
+                rows.push(row);
+                continue;
+            }
+            for record in matches {
+                let mut joined = row.clone();
+                joined.values.extend(qualify(&name, record).values);
+                rows.push(joined);
+            }
+        }
+        self.rows = rows;
+        Ok(())
+    }
+}
+
+// Lookups hash the value, so an Int is looked up in a Float column as the Float of the
+// same value, and a Float in an Int column only if it is a whole number.
+fn as_type(value: &Value, typ: &str) -> Option<Value> {
+    let converted = match (value, typ) {
+        (Value::Int(i), "Float") => Value::Float(*i as f64),
+        (Value::Float(f), "Int") => Value::Int(*f as i64),
+        _ => return Some(value.clone()),
+    };
+    value.compare(&converted).is_eq().then_some(converted)
+}
+
+fn qualify(table_name: &str, record: &Record) -> Record {
+    Record::with_values(
+        record
+            .values
+            .iter()
+            .map(|(k, v)| (format!("{}.{}", table_name, k), v.clone()))
+            .collect(),
+    )
+}
+
+// CTEs in `scope` take precedence over tables of the same name. `trace` is as for
+// `select_rows`, with the joined rows as the source.
+pub fn select_joined<K: DatabaseKey>(
+    database: &mut Database<K>,
+    scope: &Scope,
+    statement: &SelectStatement,
+    trace: &mut impl FnMut(Stage, usize),
+) -> Result<ResultSet, CustomError> {
+    let derived = match &statement.derived {
+        Some(query) => Some(execute_in(database, scope, query)?),
+        None => None,
+    };
+    let database = &*database;
+    let source = |name: &str| -> Result<Source, CustomError> {
+        match scope.get(name) {
+            Some(result) => Ok(Source::rows(name, result)),
+            None => Ok(Source::table(database.get_table(name)?)),
+        }
+    };
+    let mut joined = JoinedRows::new(match &derived {
+        Some(result) => Source::rows(&statement.table_name, result),
+        None => source(&statement.table_name)?,
+    });
+    for join in &statement.joins {
+        joined.join(source(&join.table_name)?, join)?;
+    }
+
+    // Columns are output under the name they were written with, even once resolved.
+    let mut statement = statement.expand_wildcards(&|table| joined.expand(table))?;
+    for projection in statement.fields.iter_mut() {
+        if let Projection::Operand {
+            operand,
+            alias: alias @ None,
+        } = projection
+        {
+            *alias = Some(operand.name());
+        }
+    }
+    let statement = statement.map_fields(&|name| joined.resolve(name))?;
+    trace(Stage::Source, joined.rows.len());
+    select_rows(
+        joined.rows.iter().collect(),
+        &joined.fields,
+        &statement,
+        trace,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        custom_error::CustomError,
+        database::Database,
+        test_util::{self, run},
+    };
+
+    fn database() -> Database<i64> {
+        test_util::database(&[
+            "CREATE a KEY id FIELDS id: Int, f: Float, s: String",
+            "CREATE b KEY id FIELDS id: Int, n: Int",
+            "INSERT INTO a VALUES (1, 2.0, 'x'), (2, 2.5, 'y'), (3, 1.0, 'z')",
+            "INSERT INTO b VALUES (1, 2), (2, 3)",
+        ])
+    }
+
+    #[test]
+    fn on_columns_of_different_types_are_rejected() {
+        let mut db = database();
+        let result = run(&mut db, "SELECT a.id FROM a JOIN b ON a.s = b.id");
+        assert!(matches!(result, Err(CustomError::TypeError(_))));
+    }
+
+    #[test]
+    fn ints_match_equal_floats() {
+        let mut db = database();
+        assert_eq!(
+            run(&mut db, "SELECT a.id, b.id FROM a JOIN b ON a.f = b.id").unwrap(),
+            "a.id=1, b.id=2 | a.id=3, b.id=1"
+        );
+        assert_eq!(
+            run(&mut db, "SELECT a.id, b.n FROM b JOIN a ON b.n = a.f").unwrap(),
+            "a.id=1, b.n=2"
+        );
+    }
+
+    #[test]
+    fn key_and_hash_joins_match_the_same_rows() {
+        let mut db = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int",
+            "CREATE u KEY id FIELDS id: Int, t_id: Int, n: Int",
+            "INSERT INTO t VALUES (1, 20), (2, 10), (3, 10), (4, 30)",
+            "INSERT INTO u VALUES (1, 2, 10), (2, 2, 30), (3, 4, 10), (4, 9, 20)",
+        ]);
+        let by_key = "SELECT t.id, u.id FROM u JOIN t ON u.t_id = t.id";
+        let by_hash = "SELECT t.id, u.id FROM t JOIN u ON t.n = u.n";
+        assert!(
+            run(&mut db, &format!("EXPLAIN {}", by_key))
+                .unwrap()
+                .contains(", by key t.id")
+        );
+        assert!(
+            run(&mut db, &format!("EXPLAIN {}", by_hash))
+                .unwrap()
+                .contains(", by hash")
+        );
+        assert_eq!(
+            run(&mut db, by_key).unwrap(),
+            "t.id=2, u.id=1 | t.id=2, u.id=2 | t.id=4, u.id=3"
+        );
+        assert_eq!(
+            run(&mut db, by_hash).unwrap(),
+            "t.id=1, u.id=4 | t.id=2, u.id=1 | t.id=2, u.id=3 | t.id=3, u.id=1 | t.id=3, u.id=3 | t.id=4, u.id=2"
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                "SELECT t.id, u.id FROM u LEFT JOIN t ON u.t_id = t.id WHERE u.id > 2"
+            )
+            .unwrap(),
+            "t.id=4, u.id=3 | t.id=NULL, u.id=4"
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use std::{iter::Peekable, str::CharIndices};
+
+use crate::custom_error::CustomError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keyword {
+    Create,
+    Key,
+    Fields,
+    Insert,
+    Into,
+    Values,
+    Delete,
+    From,
+    Select,
+    Where,
+    Update,
+    Set,
+    Prepare,
+    Execute,
+    Explain,
+    Analyze,
+    Conflict,
+    Do,
+    Nothing,
+    Replace,
+    Drop,
+    Table,
+    If,
+    Exists,
+    Truncate,
+    Alter,
+    Add,
+    Column,
+    Default,
+    Rename,
+    To,
+    And,
+    Or,
+    Not,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Limit,
+    Offset,
+    Group,
+    Having,
+    Join,
+    Inner,
+    Left,
+    Outer,
+    On,
+    As,
+    Distinct,
+    Union,
+    All,
+    Intersect,
+    Except,
+    Like,
+    ILike,
+    In,
+    Between,
+    Is,
+    Null,
+    Regexp,
+    Case,
+    When,
+    Then,
+    Else,
+    End,
+    With,
+    Recursive,
+    Over,
+    Partition,
+    SaveAs,
+    ReadFrom,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Keyword(Keyword),
+    Identifier(String),
+    Number(String),
+    String(String),
+    // `$1`, a value supplied when a prepared statement is executed.
+    Parameter(usize),
+    Comma,
+    Colon,
+    Semicolon,
+    LeftParen,
+    RightParen,
+    Star,
+    Dot,
+    Plus,
+    Minus,
+    Slash,
+    Eq, // =
+    Ne, // !=
+    Lt, // <
+    Le, // <=
+    Gt, // >
+    Ge, // >=
+    Tilde,
+    Unknown(char),
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Keyword {
+    pub fn from_word(word: &str) -> Option<Self> {
+        match word.to_ascii_uppercase().as_str() {
+            "CREATE" => Some(Keyword::Create),
+            "KEY" => Some(Keyword::Key),
+            "FIELDS" => Some(Keyword::Fields),
+            "INSERT" => Some(Keyword::Insert),
+            "INTO" => Some(Keyword::Into),
+            "VALUES" => Some(Keyword::Values),
+            "DELETE" => Some(Keyword::Delete),
+            "FROM" => Some(Keyword::From),
+            "SELECT" => Some(Keyword::Select),
+            "WHERE" => Some(Keyword::Where),
+            "UPDATE" => Some(Keyword::Update),
+            "SET" => Some(Keyword::Set),
+            "PREPARE" => Some(Keyword::Prepare),
+            "EXECUTE" => Some(Keyword::Execute),
+            "EXPLAIN" => Some(Keyword::Explain),
+            "ANALYZE" => Some(Keyword::Analyze),
+            "CONFLICT" => Some(Keyword::Conflict),
+            "DO" => Some(Keyword::Do),
+            "NOTHING" => Some(Keyword::Nothing),
+            "REPLACE" => Some(Keyword::Replace),
+            "DROP" => Some(Keyword::Drop),
+            "TABLE" => Some(Keyword::Table),
+            "IF" => Some(Keyword::If),
+            "EXISTS" => Some(Keyword::Exists),
+            "TRUNCATE" => Some(Keyword::Truncate),
+            "ALTER" => Some(Keyword::Alter),
+            "ADD" => Some(Keyword::Add),
+            "COLUMN" => Some(Keyword::Column),
+            "DEFAULT" => Some(Keyword::Default),
+            "RENAME" => Some(Keyword::Rename),
+            "TO" => Some(Keyword::To),
+            "AND" => Some(Keyword::And),
+            "OR" => Some(Keyword::Or),
+            "NOT" => Some(Keyword::Not),
+            "ORDER" => Some(Keyword::Order),
+            "BY" => Some(Keyword::By),
+            "ASC" => Some(Keyword::Asc),
+            "DESC" => Some(Keyword::Desc),
+            "LIMIT" => Some(Keyword::Limit),
+            "OFFSET" => Some(Keyword::Offset),
+            "GROUP" => Some(Keyword::Group),
+            "HAVING" => Some(Keyword::Having),
+            "JOIN" => Some(Keyword::Join),
+            "INNER" => Some(Keyword::Inner),
+            "LEFT" => Some(Keyword::Left),
+            "OUTER" => Some(Keyword::Outer),
+            "ON" => Some(Keyword::On),
+            "AS" => Some(Keyword::As),
+            "DISTINCT" => Some(Keyword::Distinct),
+            "UNION" => Some(Keyword::Union),
+            "ALL" => Some(Keyword::All),
+            "INTERSECT" => Some(Keyword::Intersect),
+            "EXCEPT" => Some(Keyword::Except),
+            "LIKE" => Some(Keyword::Like),
+            "ILIKE" => Some(Keyword::ILike),
+            "IN" => Some(Keyword::In),
+            "BETWEEN" => Some(Keyword::Between),
+            "IS" => Some(Keyword::Is),
+            "WITH" => Some(Keyword::With),
+            "RECURSIVE" => Some(Keyword::Recursive),
+            "OVER" => Some(Keyword::Over),
+            "PARTITION" => Some(Keyword::Partition),
+            "NULL" => Some(Keyword::Null),
+            "REGEXP" => Some(Keyword::Regexp),
+            "CASE" => Some(Keyword::Case),
+            "WHEN" => Some(Keyword::When),
+            "THEN" => Some(Keyword::Then),
+            "ELSE" => Some(Keyword::Else),
+            "END" => Some(Keyword::End),
+            "SAVE_AS" => Some(Keyword::SaveAs),
+            "READ_FROM" => Some(Keyword::ReadFrom),
+            _ => None,
+        }
+    }
+
+    // Words that start or join clauses and conditions, and so can never name a table
+    // or field. Every other keyword only means something where the grammar expects
+    // it, and is read as a name anywhere else, so a field can still be called `key`.
+    pub fn is_reserved(&self) -> bool {
+        matches!(
+            self,
+            Keyword::Create
+                | Keyword::Select
+                | Keyword::From
+                | Keyword::Where
+                | Keyword::Into
+                | Keyword::Values
+                | Keyword::And
+                | Keyword::Or
+                | Keyword::Not
+                | Keyword::Null
+                | Keyword::Is
+                | Keyword::In
+                | Keyword::Like
+                | Keyword::ILike
+                | Keyword::Between
+                | Keyword::Regexp
+                | Keyword::Exists
+                | Keyword::Case
+                | Keyword::When
+                | Keyword::Then
+                | Keyword::Else
+                | Keyword::As
+                | Keyword::On
+                | Keyword::Join
+                | Keyword::Distinct
+                | Keyword::Group
+                | Keyword::Having
+                | Keyword::Order
+                | Keyword::Limit
+                | Keyword::Offset
+                | Keyword::Union
+                | Keyword::Intersect
+                | Keyword::Except
+                | Keyword::With
+                | Keyword::Over
+                | Keyword::SaveAs
+                | Keyword::ReadFrom
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Keyword::Create => "CREATE",
+            Keyword::Key => "KEY",
+            Keyword::Fields => "FIELDS",
+            Keyword::Insert => "INSERT",
+            Keyword::Into => "INTO",
+            Keyword::Values => "VALUES",
+            Keyword::Delete => "DELETE",
+            Keyword::From => "FROM",
+            Keyword::Select => "SELECT",
+            Keyword::Where => "WHERE",
+            Keyword::Update => "UPDATE",
+            Keyword::Set => "SET",
+            Keyword::Prepare => "PREPARE",
+            Keyword::Execute => "EXECUTE",
+            Keyword::Explain => "EXPLAIN",
+            Keyword::Analyze => "ANALYZE",
+            Keyword::Conflict => "CONFLICT",
+            Keyword::Do => "DO",
+            Keyword::Nothing => "NOTHING",
+            Keyword::Replace => "REPLACE",
+            Keyword::Drop => "DROP",
+            Keyword::Table => "TABLE",
+            Keyword::If => "IF",
+            Keyword::Exists => "EXISTS",
+            Keyword::Truncate => "TRUNCATE",
+            Keyword::Alter => "ALTER",
+            Keyword::Add => "ADD",
+            Keyword::Column => "COLUMN",
+            Keyword::Default => "DEFAULT",
+            Keyword::Rename => "RENAME",
+            Keyword::To => "TO",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Not => "NOT",
+            Keyword::Order => "ORDER",
+            Keyword::By => "BY",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Limit => "LIMIT",
+            Keyword::Offset => "OFFSET",
+            Keyword::Group => "GROUP",
+            Keyword::Having => "HAVING",
+            Keyword::Join => "JOIN",
+            Keyword::Inner => "INNER",
+            Keyword::Left => "LEFT",
+            Keyword::Outer => "OUTER",
+            Keyword::On => "ON",
+            Keyword::As => "AS",
+            Keyword::Distinct => "DISTINCT",
+            Keyword::Union => "UNION",
+            Keyword::All => "ALL",
+            Keyword::Intersect => "INTERSECT",
+            Keyword::Except => "EXCEPT",
+            Keyword::Like => "LIKE",
+            Keyword::ILike => "ILIKE",
+            Keyword::In => "IN",
+            Keyword::Between => "BETWEEN",
+            Keyword::Is => "IS",
+            Keyword::With => "WITH",
+            Keyword::Recursive => "RECURSIVE",
+            Keyword::Over => "OVER",
+            Keyword::Partition => "PARTITION",
+            Keyword::Null => "NULL",
+            Keyword::Regexp => "REGEXP",
+            Keyword::Case => "CASE",
+            Keyword::When => "WHEN",
+            Keyword::Then => "THEN",
+            Keyword::Else => "ELSE",
+            Keyword::End => "END",
+            Keyword::SaveAs => "SAVE_AS",
+            Keyword::ReadFrom => "READ_FROM",
+        }
+    }
+}
+
+// Writes `text` as a double-quoted literal that lexes back to exactly `text`.
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl TokenKind {
+    pub fn describe(&self) -> String {
+        match self {
+            TokenKind::Keyword(k) => k.as_str().to_string(),
+            TokenKind::Identifier(s) => format!("'{}'", s),
+            TokenKind::Number(n) => n.to_string(),
+            TokenKind::String(s) => format!("string \"{}\"", s),
+            TokenKind::Parameter(n) => format!("${}", n),
+            TokenKind::Comma => "','".to_string(),
+            TokenKind::Colon => "':'".to_string(),
+            TokenKind::Semicolon => "';'".to_string(),
+            TokenKind::LeftParen => "'('".to_string(),
+            TokenKind::RightParen => "')'".to_string(),
+            TokenKind::Star => "'*'".to_string(),
+            TokenKind::Dot => "'.'".to_string(),
+            TokenKind::Plus => "'+'".to_string(),
+            TokenKind::Minus => "'-'".to_string(),
+            TokenKind::Slash => "'/'".to_string(),
+            TokenKind::Eq => "'='".to_string(),
+            TokenKind::Ne => "'!='".to_string(),
+            TokenKind::Lt => "'<'".to_string(),
+            TokenKind::Le => "'<='".to_string(),
+            TokenKind::Gt => "'>'".to_string(),
+            TokenKind::Ge => "'>='".to_string(),
+            TokenKind::Tilde => "'~'".to_string(),
+            TokenKind::Unknown(c) => format!("'{}'", c),
+            TokenKind::Eof => "end of input".to_string(),
+        }
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<Token>, CustomError> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            let done = token.kind == TokenKind::Eof;
+            tokens.push(token);
+            if done {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn peek_char(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek_char() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Token, CustomError> {
+        self.skip_whitespace();
+
+        let line = self.line;
+        let column = self.column;
+        let offset = match self.chars.peek() {
+            Some((i, _)) => *i,
+            None => self.input.len(),
+        };
+        let token = |kind| Token {
+            kind,
+            line,
+            column,
+            offset,
+        };
+
+        let Some(c) = self.bump() else {
+            return Ok(token(TokenKind::Eof));
+        };
+
+        let kind = match c {
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            ';' => TokenKind::Semicolon,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '*' => TokenKind::Star,
+            '.' => TokenKind::Dot,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '/' => TokenKind::Slash,
+            '=' => TokenKind::Eq,
+            '!' if self.peek_char() == Some('=') => {
+                self.bump();
+                TokenKind::Ne
+            }
+            '<' if self.peek_char() == Some('=') => {
+                self.bump();
+                TokenKind::Le
+            }
+            '<' => TokenKind::Lt,
+            '>' if self.peek_char() == Some('=') => {
+                self.bump();
+                TokenKind::Ge
+            }
+            '>' => TokenKind::Gt,
+            '~' => TokenKind::Tilde,
+            // A quote is escaped by doubling it or with a backslash; other backslash
+            // sequences that are not listed stay as written, so `'^\d+$'` is a regex.
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match self.bump() {
+                        Some(q) if q == c && self.peek_char() == Some(c) => {
+                            self.bump();
+                            text.push(c);
+                        }
+                        Some(q) if q == c => break,
+                        Some('\\') => match self.bump() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some('r') => text.push('\r'),
+                            Some('0') => text.push('\0'),
+                            Some(ch @ ('\\' | '\'' | '"')) => text.push(ch),
+                            Some(ch) => {
+                                text.push('\\');
+                                text.push(ch);
+                            }
+                            None => text.push('\\'),
+                        },
+                        Some(ch) => text.push(ch),
+                        None => {
+                            return Err(CustomError::SyntaxError {
+                                message: "unterminated string literal".to_string(),
+                                line,
+                                column,
+                            });
+                        }
+                    }
+                }
+                TokenKind::String(text)
+            }
+            '$' if self.peek_char().is_some_and(|d| d.is_ascii_digit()) => {
+                let mut text = String::new();
+                while let Some(d) = self.peek_char().filter(|d| d.is_ascii_digit()) {
+                    text.push(d);
+                    self.bump();
+                }
+                match text.parse::<usize>() {
+                    Ok(n) if n > 0 => TokenKind::Parameter(n),
+                    _ => {
+                        return Err(CustomError::SyntaxError {
+                            message: format!(
+                                "invalid parameter ${}, they are numbered from $1",
+                                text
+                            ),
+                            line,
+                            column,
+                        });
+                    }
+                }
+            }
+            c if c.is_ascii_digit() => {
+                let mut text = c.to_string();
+                while let Some(d) = self.peek_char() {
+                    if !d.is_ascii_digit() && d != '.' {
+                        break;
+                    }
+                    text.push(d);
+                    self.bump();
+                }
+                TokenKind::Number(text)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut text = c.to_string();
+                while let Some(d) = self.peek_char() {
+                    if !d.is_alphanumeric() && d != '_' {
+                        break;
+                    }
+                    text.push(d);
+                    self.bump();
+                }
+                match Keyword::from_word(&text) {
+                    Some(k) => TokenKind::Keyword(k),
+                    None => TokenKind::Identifier(text),
+                }
+            }
+            c => TokenKind::Unknown(c),
+        };
+
+        Ok(token(kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::custom_error::CustomError;
+
+    use super::{Keyword, Lexer, TokenKind, quote};
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Lexer::new(input)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn splits_words_numbers_and_operators() {
+        assert_eq!(
+            kinds("select KEYS from t where n >= 2.5"),
+            [
+                TokenKind::Keyword(Keyword::Select),
+                TokenKind::Identifier("KEYS".to_string()),
+                TokenKind::Keyword(Keyword::From),
+                TokenKind::Identifier("t".to_string()),
+                TokenKind::Keyword(Keyword::Where),
+                TokenKind::Identifier("n".to_string()),
+                TokenKind::Ge,
+                TokenKind::Number("2.5".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_inside_strings_stay_text() {
+        assert_eq!(
+            kinds("'FROM x KEY'"),
+            [TokenKind::String("FROM x KEY".to_string()), TokenKind::Eof]
+        );
+    }
+
+    #[test]
+    fn escapes_in_quoted_literals() {
+        assert_eq!(
+            kinds(r#"'it''s' "say \"hi\"" 'a\nb\t\\' '^\d+$'"#),
+            [
+                TokenKind::String("it's".to_string()),
+                TokenKind::String("say \"hi\"".to_string()),
+                TokenKind::String("a\nb\t\\".to_string()),
+                TokenKind::String("^\\d+$".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_text_lexes_back_unchanged() {
+        for text in [
+            "plain",
+            "it's",
+            "\"x\"",
+            "a\\b",
+            "line\nbreak\ttab\r\0",
+            "^\\d+$",
+            "",
+        ] {
+            assert_eq!(
+                kinds(&quote(text)),
+                [TokenKind::String(text.to_string()), TokenKind::Eof]
+            );
+        }
+    }
+
+    #[test]
+    fn tokens_carry_line_and_column() {
+        let tokens = Lexer::new("SELECT a\n  FROM t").tokenize().unwrap();
+        let positions = tokens
+            .iter()
+            .map(|t| (t.line, t.column))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [(1, 1), (1, 8), (2, 3), (2, 8), (2, 9)]);
+    }
+
+    #[test]
+    fn unterminated_string_points_at_its_quote() {
+        let result = Lexer::new("SELECT a\nFROM t WHERE s = 'abc").tokenize();
+        assert!(matches!(
+            result,
+            Err(CustomError::SyntaxError {
+                line: 2,
+                column: 18,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parameters_start_at_one() {
+        assert_eq!(kinds("$12"), [TokenKind::Parameter(12), TokenKind::Eof]);
+        assert!(matches!(
+            Lexer::new("a = $0").tokenize(),
+            Err(CustomError::SyntaxError {
+                line: 1,
+                column: 5,
+                ..
+            })
+        ));
+    }
+}
@@ -1,11 +1,23 @@
+pub mod aggregate;
 pub mod app;
 pub mod arguments;
+pub mod ast;
 pub mod command_history;
 pub mod commands;
 pub mod condition;
 pub mod custom_error;
 pub mod database;
 pub mod event_handler;
+pub mod explain;
+pub mod expression;
 pub mod handlers;
+pub mod join;
+pub mod lexer;
+pub mod parser;
 pub mod parsers;
+pub mod prepared;
+pub mod query;
+#[cfg(test)]
+mod test_util;
 pub mod ui;
+pub mod window;
@@ -0,0 +1,1297 @@
+use crate::{
+    ast::{
+        Aggregate, AggregateFunction, AlterAction, AlterStatement, ArithmeticOp, Assignment, Case,
+        CaseWhen, Column, CommonTableExpression, Comparison, CompoundQuery, CreateStatement,
+        DeleteStatement, DropStatement, Expr, InsertRows, InsertStatement, Join, JoinKind, Literal,
+        OnConflict, Operand, OrderBy, Projection, Query, ScalarFunction, SelectStatement,
+        SetOperator, Statement, Subquery, TruncateStatement, UpdateStatement, Window,
+        WindowFunction, WithQuery,
+    },
+    condition::Op,
+    custom_error::CustomError,
+    lexer::{Keyword, Lexer, Token, TokenKind},
+};
+
+pub struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+pub fn parse(input: &str) -> Result<Statement, CustomError> {
+    Parser::new(input)?.parse_statement()
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Result<Self, CustomError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        Ok(Self {
+            source,
+            tokens,
+            position: 0,
+        })
+    }
+
+    pub fn parse_statement(&mut self) -> Result<Statement, CustomError> {
+        let statement = match self.peek().kind {
+            TokenKind::Keyword(Keyword::Create) => Statement::Create(self.parse_create()?),
+            TokenKind::Keyword(Keyword::Insert | Keyword::Replace) => {
+                Statement::Insert(self.parse_insert()?)
+            }
+            TokenKind::Keyword(Keyword::Delete) => Statement::Delete(self.parse_delete()?),
+            TokenKind::Keyword(Keyword::Select | Keyword::With) => {
+                Statement::Select(self.parse_query()?)
+            }
+            TokenKind::Keyword(Keyword::Update) => Statement::Update(self.parse_update()?),
+            TokenKind::Keyword(Keyword::Drop) => Statement::Drop(self.parse_drop()?),
+            TokenKind::Keyword(Keyword::Truncate) => Statement::Truncate(self.parse_truncate()?),
+            TokenKind::Keyword(Keyword::Alter) => Statement::Alter(self.parse_alter()?),
+            TokenKind::Keyword(Keyword::SaveAs) => {
+                self.advance();
+                return Ok(Statement::SaveAs(self.rest_of_input()?));
+            }
+            TokenKind::Keyword(Keyword::ReadFrom) => {
+                self.advance();
+                return Ok(Statement::ReadFrom(self.rest_of_input()?));
+            }
+            TokenKind::Keyword(Keyword::Prepare) => {
+                self.advance();
+                let name = self.expect_identifier("statement name")?;
+                self.expect_keyword(Keyword::As)?;
+                let statement = Box::new(self.parse_statement()?);
+                return Ok(Statement::Prepare { name, statement });
+            }
+            TokenKind::Keyword(Keyword::Execute) => self.parse_execute()?,
+            TokenKind::Keyword(Keyword::Explain) => {
+                self.advance();
+                let analyze = self.consume_keyword(Keyword::Analyze);
+                if !matches!(
+                    self.peek().kind,
+                    TokenKind::Keyword(Keyword::Select | Keyword::With)
+                ) {
+                    return Err(self.unexpected("SELECT or WITH"));
+                }
+                Statement::Explain {
+                    analyze,
+                    query: self.parse_query()?,
+                }
+            }
+            _ => return Err(CustomError::UnknownCommand(self.source.trim().to_string())),
+        };
+
+        self.consume(&TokenKind::Semicolon);
+        if self.peek().kind != TokenKind::Eof {
+            return Err(self.unexpected("end of input"));
+        }
+        Ok(statement)
+    }
+
+    fn parse_create(&mut self) -> Result<CreateStatement, CustomError> {
+        self.expect_keyword(Keyword::Create)?;
+        let table_name = self.expect_identifier("table name")?;
+        self.expect_keyword(Keyword::Key)?;
+        let key_field = self.expect_identifier("key field")?;
+        self.expect_keyword(Keyword::Fields)?;
+
+        let mut fields = Vec::new();
+        loop {
+            let name = self.expect_identifier("field name")?;
+            self.expect(&TokenKind::Colon, "':'")?;
+            let typ = self.expect_identifier("field type")?;
+            fields.push((name, typ));
+            if !self.consume(&TokenKind::Comma) {
+                break;
+            }
+        }
+
+        Ok(CreateStatement {
+            table_name,
+            key_field,
+            fields,
+        })
+    }
+
+    fn parse_insert(&mut self) -> Result<InsertStatement, CustomError> {
+        let replace = self.consume_keyword(Keyword::Replace);
+        if replace {
+            self.expect_keyword(Keyword::Into)?;
+        } else {
+            self.expect_keyword(Keyword::Insert)?;
+        }
+        let (table_name, columns, rows) = match replace || self.consume_keyword(Keyword::Into) {
+            true => self.parse_insert_rows()?,
+            false => {
+                let assignments = self.parse_assignments(true)?;
+                self.expect_keyword(Keyword::Into)?;
+                let table_name = self.expect_identifier("table name")?;
+                let (columns, row) = assignments
+                    .into_iter()
+                    .map(|a| (a.field, Some(a.value)))
+                    .unzip();
+                (table_name, columns, InsertRows::Values(vec![row]))
+            }
+        };
+
+        let (conflict_target, on_conflict) = match replace {
+            true => (None, OnConflict::Replace),
+            false => self.parse_on_conflict()?,
+        };
+        Ok(InsertStatement {
+            table_name,
+            columns,
+            rows,
+            conflict_target,
+            on_conflict,
+        })
+    }
+
+    // t [(columns)] VALUES (...), ... | SELECT ...
+    fn parse_insert_rows(&mut self) -> Result<(String, Vec<String>, InsertRows), CustomError> {
+        let table_name = self.expect_identifier("table name")?;
+        let mut columns = Vec::new();
+        if self.consume(&TokenKind::LeftParen) {
+            loop {
+                columns.push(self.expect_identifier("column name")?);
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(&TokenKind::RightParen, "')'")?;
+        }
+        let rows = match self.peek().kind {
+            TokenKind::Keyword(Keyword::Values) => {
+                self.advance();
+                let mut rows = Vec::new();
+                loop {
+                    rows.push(self.parse_values_row()?);
+                    if !self.consume(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                InsertRows::Values(rows)
+            }
+            TokenKind::Keyword(Keyword::Select | Keyword::With) => {
+                InsertRows::Query(self.parse_query()?)
+            }
+            _ => return Err(self.unexpected("VALUES or SELECT")),
+        };
+        Ok((table_name, columns, rows))
+    }
+
+    // [ON CONFLICT [(key)] DO NOTHING | DO UPDATE SET field = operand, ...]
+    fn parse_on_conflict(&mut self) -> Result<(Option<String>, OnConflict), CustomError> {
+        if !self.consume_keyword(Keyword::On) {
+            return Ok((None, OnConflict::Fail));
+        }
+        self.expect_keyword(Keyword::Conflict)?;
+        let mut target = None;
+        if self.consume(&TokenKind::LeftParen) {
+            target = Some(self.expect_identifier("key field")?);
+            self.expect(&TokenKind::RightParen, "')'")?;
+        }
+        self.expect_keyword(Keyword::Do)?;
+        if self.consume_keyword(Keyword::Nothing) {
+            return Ok((target, OnConflict::Nothing));
+        }
+        self.expect_keyword(Keyword::Update)?;
+        self.expect_keyword(Keyword::Set)?;
+        let mut assignments = Vec::new();
+        loop {
+            let field = self.expect_identifier("field name")?;
+            self.expect(&TokenKind::Eq, "'='")?;
+            assignments.push((field, self.parse_operand()?));
+            if !self.consume(&TokenKind::Comma) {
+                return Ok((target, OnConflict::Update(assignments)));
+            }
+        }
+    }
+
+    // (value, NULL, ...)
+    fn parse_values_row(&mut self) -> Result<Vec<Option<Literal>>, CustomError> {
+        self.expect(&TokenKind::LeftParen, "'('")?;
+        let mut row = Vec::new();
+        loop {
+            row.push(match self.consume_keyword(Keyword::Null) {
+                true => None,
+                false => Some(self.parse_value()?),
+            });
+            if !self.consume(&TokenKind::Comma) {
+                self.expect(&TokenKind::RightParen, "')'")?;
+                return Ok(row);
+            }
+        }
+    }
+
+    // EXECUTE name [(value, ...)]
+    fn parse_execute(&mut self) -> Result<Statement, CustomError> {
+        self.expect_keyword(Keyword::Execute)?;
+        let name = self.expect_identifier("statement name")?;
+        let mut arguments = Vec::new();
+        if self.consume(&TokenKind::LeftParen) && !self.consume(&TokenKind::RightParen) {
+            loop {
+                arguments.push(self.parse_value()?);
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(&TokenKind::RightParen, "')'")?;
+        }
+        Ok(Statement::Execute { name, arguments })
+    }
+
+    fn parse_delete(&mut self) -> Result<DeleteStatement, CustomError> {
+        self.expect_keyword(Keyword::Delete)?;
+        if self.consume_keyword(Keyword::From) {
+            let table_name = self.expect_identifier("table name")?;
+            let condition = self.parse_where()?;
+            return Ok(DeleteStatement {
+                table_name,
+                key: None,
+                condition,
+            });
+        }
+        let key = self.parse_literal()?;
+        self.expect_keyword(Keyword::From)?;
+        let table_name = self.expect_identifier("table name")?;
+
+        Ok(DeleteStatement {
+            table_name,
+            key: Some(key),
+            condition: None,
+        })
+    }
+
+    // INTERSECT binds tighter than UNION and EXCEPT, which associate to the left.
+    fn parse_query(&mut self) -> Result<Query, CustomError> {
+        if self.consume_keyword(Keyword::With) {
+            let recursive = self.consume_keyword_before_name(Keyword::Recursive);
+            let mut ctes = vec![self.parse_cte()?];
+            while self.consume(&TokenKind::Comma) {
+                ctes.push(self.parse_cte()?);
+            }
+            return Ok(Query::With(Box::new(WithQuery {
+                recursive,
+                ctes,
+                body: self.parse_query()?,
+            })));
+        }
+        let mut query = self.parse_intersection()?;
+        loop {
+            let operator = match self.peek().kind {
+                TokenKind::Keyword(Keyword::Union) => SetOperator::Union,
+                TokenKind::Keyword(Keyword::Except) => SetOperator::Except,
+                _ => break,
+            };
+            self.advance();
+            let all = self.consume_keyword(Keyword::All);
+            let right = self.parse_intersection()?;
+            query = Query::Compound(Box::new(CompoundQuery {
+                operator,
+                all,
+                left: query,
+                right,
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+            }));
+        }
+
+        if let Query::Compound(compound) = &mut query {
+            let mut last = &mut compound.right;
+            while let Query::Compound(inner) = last {
+                last = &mut inner.right;
+            }
+            if let Query::Select(select) = last {
+                compound.order_by = std::mem::take(&mut select.order_by);
+                compound.limit = select.limit.take();
+                compound.offset = std::mem::take(&mut select.offset);
+            }
+        }
+        Ok(query)
+    }
+
+    // name [(column, ...)] AS (query)
+    fn parse_cte(&mut self) -> Result<CommonTableExpression, CustomError> {
+        let name = self.expect_identifier("CTE name")?;
+        let mut columns = Vec::new();
+        if self.consume(&TokenKind::LeftParen) {
+            loop {
+                columns.push(self.expect_identifier("column name")?);
+                if !self.consume(&TokenKind::Comma) {
+                    break;
+                }
+            }
+            self.expect(&TokenKind::RightParen, "')'")?;
+        }
+        self.expect_keyword(Keyword::As)?;
+        let query = self.parse_subquery()?.query;
+        Ok(CommonTableExpression {
+            name,
+            columns,
+            query,
+        })
+    }
+
+    fn parse_intersection(&mut self) -> Result<Query, CustomError> {
+        let mut query = Query::Select(Box::new(self.parse_set_operand()?));
+        while self.consume_keyword(Keyword::Intersect) {
+            let all = self.consume_keyword(Keyword::All);
+            let right = Query::Select(Box::new(self.parse_set_operand()?));
+            query = Query::Compound(Box::new(CompoundQuery {
+                operator: SetOperator::Intersect,
+                all,
+                left: query,
+                right,
+                order_by: Vec::new(),
+                limit: None,
+                offset: 0,
+            }));
+        }
+        Ok(query)
+    }
+
+    fn parse_set_operand(&mut self) -> Result<SelectStatement, CustomError> {
+        let select = self.parse_select()?;
+        let followed_by_operator = matches!(
+            self.peek().kind,
+            TokenKind::Keyword(Keyword::Union | Keyword::Intersect | Keyword::Except)
+        );
+        if followed_by_operator && (!select.order_by.is_empty() || select.limit.is_some()) {
+            let token = self.peek();
+            return Err(CustomError::SyntaxError {
+                message: "ORDER BY and LIMIT must follow the last SELECT".to_string(),
+                line: token.line,
+                column: token.column,
+            });
+        }
+        Ok(select)
+    }
+
+    fn parse_select(&mut self) -> Result<SelectStatement, CustomError> {
+        self.expect_keyword(Keyword::Select)?;
+        let distinct = self.consume_keyword(Keyword::Distinct);
+
+        let mut fields = vec![self.parse_projection()?];
+        while self.consume(&TokenKind::Comma) {
+            fields.push(self.parse_projection()?);
+        }
+
+        self.expect_keyword(Keyword::From)?;
+        let (table_name, derived) = if self.peek().kind == TokenKind::LeftParen {
+            let query = self.parse_subquery()?.query;
+            self.consume_keyword(Keyword::As);
+            (self.expect_identifier("alias")?, Some(Box::new(query)))
+        } else {
+            (self.expect_identifier("table name")?, None)
+        };
+
+        let mut joins = Vec::new();
+        while let Some(join) = self.parse_join()? {
+            joins.push(join);
+        }
+
+        let condition = self.parse_where()?;
+        let mut group_by = Vec::new();
+        if self.consume_keyword(Keyword::Group) {
+            self.expect_keyword(Keyword::By)?;
+            group_by.push(self.parse_field_name()?);
+            while self.consume(&TokenKind::Comma) {
+                group_by.push(self.parse_field_name()?);
+            }
+        }
+        let having = if self.consume_keyword(Keyword::Having) {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+        let mut order_by = self.parse_order_by()?;
+        for key in order_by.iter_mut() {
+            let Operand::Column(Column::Field(name)) = &key.operand else {
+                continue;
+            };
+            let aliased = fields.iter().find_map(|p| match p {
+                Projection::Operand {
+                    operand,
+                    alias: Some(alias),
+                } if alias == name => Some(operand.clone()),
+                _ => None,
+            });
+            if let Some(operand) = aliased {
+                key.operand = operand;
+            }
+        }
+        let (limit, offset) = if self.consume_keyword(Keyword::Limit) {
+            let limit = self.expect_count()?;
+            let offset = if self.consume_keyword(Keyword::Offset) {
+                self.expect_count()?
+            } else {
+                0
+            };
+            (Some(limit), offset)
+        } else {
+            (None, 0)
+        };
+
+        Ok(SelectStatement {
+            distinct,
+            table_name,
+            derived,
+            joins,
+            fields,
+            condition,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_join(&mut self) -> Result<Option<Join>, CustomError> {
+        let kind = match self.peek().kind {
+            TokenKind::Keyword(Keyword::Join) => JoinKind::Inner,
+            TokenKind::Keyword(Keyword::Inner) => {
+                self.advance();
+                JoinKind::Inner
+            }
+            TokenKind::Keyword(Keyword::Left) => {
+                self.advance();
+                self.consume_keyword(Keyword::Outer);
+                JoinKind::Left
+            }
+            _ => return Ok(None),
+        };
+        self.expect_keyword(Keyword::Join)?;
+        let table_name = self.expect_identifier("table name")?;
+        self.expect_keyword(Keyword::On)?;
+        let left = self.parse_field_name()?;
+        self.expect(&TokenKind::Eq, "'='")?;
+        let right = self.parse_field_name()?;
+
+        Ok(Some(Join {
+            kind,
+            table_name,
+            left,
+            right,
+        }))
+    }
+
+    fn parse_update(&mut self) -> Result<UpdateStatement, CustomError> {
+        self.expect_keyword(Keyword::Update)?;
+        let table_name = self.expect_identifier("table name")?;
+        self.expect_keyword(Keyword::Set)?;
+        let assignments = self.parse_assignments(false)?;
+        let condition = self.parse_where()?;
+
+        Ok(UpdateStatement {
+            table_name,
+            assignments,
+            condition,
+        })
+    }
+
+    fn parse_drop(&mut self) -> Result<DropStatement, CustomError> {
+        self.expect_keyword(Keyword::Drop)?;
+        self.expect_keyword(Keyword::Table)?;
+        let if_exists = self.peek().kind == TokenKind::Keyword(Keyword::If)
+            && self.peek_ahead(1).map(|t| &t.kind) == Some(&TokenKind::Keyword(Keyword::Exists));
+        if if_exists {
+            self.position += 2;
+        }
+        let table_name = self.expect_identifier("table name")?;
+
+        Ok(DropStatement {
+            table_name,
+            if_exists,
+        })
+    }
+
+    fn parse_truncate(&mut self) -> Result<TruncateStatement, CustomError> {
+        self.expect_keyword(Keyword::Truncate)?;
+        self.consume_keyword_before_name(Keyword::Table);
+        let table_name = self.expect_identifier("table name")?;
+
+        Ok(TruncateStatement { table_name })
+    }
+
+    fn parse_alter(&mut self) -> Result<AlterStatement, CustomError> {
+        self.expect_keyword(Keyword::Alter)?;
+        self.expect_keyword(Keyword::Table)?;
+        let table_name = self.expect_identifier("table name")?;
+
+        let action = match self.peek().kind {
+            TokenKind::Keyword(Keyword::Add) => {
+                self.advance();
+                self.consume_keyword_before_name(Keyword::Column);
+                let name = self.expect_identifier("column name")?;
+                self.consume(&TokenKind::Colon);
+                let typ = self.expect_identifier("column type")?;
+                let default = if self.consume_keyword(Keyword::Default) {
+                    Some(self.parse_value()?)
+                } else {
+                    None
+                };
+                AlterAction::AddColumn { name, typ, default }
+            }
+            TokenKind::Keyword(Keyword::Drop) => {
+                self.advance();
+                self.consume_keyword_before_name(Keyword::Column);
+                AlterAction::DropColumn(self.expect_identifier("column name")?)
+            }
+            TokenKind::Keyword(Keyword::Rename) => {
+                self.advance();
+                if self.consume_keyword(Keyword::To) {
+                    AlterAction::RenameTo(self.expect_identifier("table name")?)
+                } else {
+                    self.expect_keyword(Keyword::Column)?;
+                    let from = self.expect_identifier("column name")?;
+                    self.expect_keyword(Keyword::To)?;
+                    let to = self.expect_identifier("column name")?;
+                    AlterAction::RenameColumn { from, to }
+                }
+            }
+            _ => return Err(self.unexpected("ADD, DROP or RENAME")),
+        };
+
+        Ok(AlterStatement { table_name, action })
+    }
+
+    fn parse_where(&mut self) -> Result<Option<Expr>, CustomError> {
+        if !self.consume_keyword(Keyword::Where) {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_or()?))
+    }
+
+    fn parse_order_by(&mut self) -> Result<Vec<OrderBy>, CustomError> {
+        let mut order_by = Vec::new();
+        if !self.consume_keyword(Keyword::Order) {
+            return Ok(order_by);
+        }
+        self.expect_keyword(Keyword::By)?;
+        loop {
+            let operand = self.parse_operand()?;
+            let descending = if self.consume_keyword(Keyword::Desc) {
+                true
+            } else {
+                self.consume_keyword(Keyword::Asc);
+                false
+            };
+            order_by.push(OrderBy {
+                operand,
+                descending,
+            });
+            if !self.consume(&TokenKind::Comma) {
+                return Ok(order_by);
+            }
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, CustomError> {
+        let mut expr = self.parse_and()?;
+        while self.consume_keyword(Keyword::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, CustomError> {
+        let mut expr = self.parse_not()?;
+        while self.consume_keyword(Keyword::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, CustomError> {
+        if self.consume_keyword(Keyword::Not) {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        if self.consume_keyword(Keyword::Exists) {
+            return Ok(Expr::Exists(Box::new(self.parse_subquery()?)));
+        }
+        // A parenthesis opens either a nested condition or an operand such as
+        // `(pages + 1) * 2 > 10`; try the condition first and fall back.
+        if self.peek().kind == TokenKind::LeftParen {
+            let start = self.position;
+            self.advance();
+            let nested = self
+                .parse_or()
+                .and_then(|expr| self.expect(&TokenKind::RightParen, "')'").map(|_| expr));
+            match nested {
+                Ok(expr) if !self.at_operator() => return Ok(expr),
+                _ => self.position = start,
+            }
+        }
+        self.parse_comparison()
+    }
+
+    // Only the legacy `INSERT field = value, ... INTO` form still reads a bare word as
+    // text. It is kept as quoted text, so the VALUES form it is saved in reads it back.
+    fn parse_assignments(&mut self, legacy: bool) -> Result<Vec<Assignment>, CustomError> {
+        let mut assignments = Vec::new();
+        loop {
+            let field = self.expect_identifier("field name")?;
+            self.expect(&TokenKind::Eq, "'='")?;
+            let value = match legacy {
+                true => match self.parse_literal()? {
+                    Literal::Word(w)
+                        if !w.eq_ignore_ascii_case("true") && !w.eq_ignore_ascii_case("false") =>
+                    {
+                        Literal::Quoted(w)
+                    }
+                    literal => literal,
+                },
+                false => self.parse_value()?,
+            };
+            assignments.push(Assignment { field, value });
+            if !self.consume(&TokenKind::Comma) {
+                return Ok(assignments);
+            }
+        }
+    }
+
+    fn parse_field_name(&mut self) -> Result<String, CustomError> {
+        let name = self.expect_identifier("field name")?;
+        if self.consume(&TokenKind::Dot) {
+            let field = self.expect_identifier("field name")?;
+            return Ok(format!("{}.{}", name, field));
+        }
+        Ok(name)
+    }
+
+    fn parse_projection(&mut self) -> Result<Projection, CustomError> {
+        if self.consume(&TokenKind::Star) {
+            return Ok(Projection::Wildcard(None));
+        }
+        if let (Some(table), Some(TokenKind::Dot), Some(TokenKind::Star)) = (
+            self.name_at(0),
+            self.peek_ahead(1).map(|t| &t.kind),
+            self.peek_ahead(2).map(|t| &t.kind),
+        ) {
+            self.position += 3;
+            return Ok(Projection::Wildcard(Some(table)));
+        }
+
+        let operand = self.parse_operand()?;
+        let alias = if self.consume_keyword(Keyword::As) {
+            Some(self.expect_identifier("alias")?)
+        } else {
+            None
+        };
+        Ok(Projection::Operand { operand, alias })
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand, CustomError> {
+        let mut operand = self.parse_term()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Plus => ArithmeticOp::Add,
+                TokenKind::Minus => ArithmeticOp::Subtract,
+                _ => return Ok(operand),
+            };
+            self.advance();
+            operand = Operand::Arithmetic {
+                op,
+                left: Box::new(operand),
+                right: Box::new(self.parse_term()?),
+            };
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Operand, CustomError> {
+        let mut operand = self.parse_factor()?;
+        loop {
+            let op = match self.peek().kind {
+                TokenKind::Star => ArithmeticOp::Multiply,
+                TokenKind::Slash => ArithmeticOp::Divide,
+                _ => return Ok(operand),
+            };
+            self.advance();
+            operand = Operand::Arithmetic {
+                op,
+                left: Box::new(operand),
+                right: Box::new(self.parse_factor()?),
+            };
+        }
+    }
+
+    fn parse_factor(&mut self) -> Result<Operand, CustomError> {
+        match &self.peek().kind {
+            TokenKind::Minus => {
+                if let Some(TokenKind::Number(_)) = self.peek_ahead(1).map(|t| &t.kind) {
+                    return Ok(Operand::Literal(self.parse_literal()?));
+                }
+                self.advance();
+                Ok(Operand::Negate(Box::new(self.parse_factor()?)))
+            }
+            TokenKind::LeftParen if self.starts_subquery() => {
+                Ok(Operand::Subquery(Box::new(self.parse_subquery()?)))
+            }
+            TokenKind::LeftParen => {
+                self.advance();
+                let operand = self.parse_operand()?;
+                self.expect(&TokenKind::RightParen, "')'")?;
+                Ok(operand)
+            }
+            TokenKind::Number(_) | TokenKind::String(_) | TokenKind::Parameter(_) => {
+                Ok(Operand::Literal(self.parse_literal()?))
+            }
+            TokenKind::Keyword(Keyword::Case) => self.parse_case(),
+            TokenKind::Keyword(Keyword::Null) => {
+                self.advance();
+                Ok(Operand::Null)
+            }
+            _ => self.parse_call_or_column(),
+        }
+    }
+
+    fn starts_subquery(&self) -> bool {
+        self.peek().kind == TokenKind::LeftParen
+            && matches!(
+                self.peek_ahead(1).map(|t| &t.kind),
+                Some(TokenKind::Keyword(Keyword::Select | Keyword::With))
+            )
+    }
+
+    fn parse_subquery(&mut self) -> Result<Subquery, CustomError> {
+        self.expect(&TokenKind::LeftParen, "'('")?;
+        let query = self.parse_query()?;
+        self.expect(&TokenKind::RightParen, "')'")?;
+        Ok(Subquery {
+            query,
+            result: None,
+        })
+    }
+
+    fn parse_case(&mut self) -> Result<Operand, CustomError> {
+        self.expect_keyword(Keyword::Case)?;
+        let subject = match self.peek().kind {
+            TokenKind::Keyword(Keyword::When) => None,
+            _ => Some(self.parse_operand()?),
+        };
+        let mut branches = Vec::new();
+        while self.consume_keyword(Keyword::When) {
+            let when = match subject {
+                Some(_) => CaseWhen::Value(self.parse_operand()?),
+                None => CaseWhen::Condition(self.parse_or()?),
+            };
+            self.expect_keyword(Keyword::Then)?;
+            branches.push((when, self.parse_operand()?));
+        }
+        if branches.is_empty() {
+            return Err(self.unexpected("WHEN"));
+        }
+        let otherwise = match self.consume_keyword(Keyword::Else) {
+            true => Some(self.parse_operand()?),
+            false => None,
+        };
+        self.expect_keyword(Keyword::End)?;
+        Ok(Operand::Case(Box::new(Case {
+            subject,
+            branches,
+            otherwise,
+        })))
+    }
+
+    fn parse_call_or_column(&mut self) -> Result<Operand, CustomError> {
+        let name_token = self.peek().clone();
+        let name = self.parse_field_name()?;
+        if !self.consume(&TokenKind::LeftParen) {
+            return Ok(Operand::Column(Column::Field(name)));
+        }
+
+        if let Some(function) = AggregateFunction::from_name(&name) {
+            let field = if function == AggregateFunction::Count && self.consume(&TokenKind::Star) {
+                None
+            } else {
+                Some(self.parse_field_name()?)
+            };
+            self.expect(&TokenKind::RightParen, "')'")?;
+            let aggregate = Aggregate { function, field };
+            if self.peek().kind == TokenKind::Keyword(Keyword::Over) {
+                return self.parse_over(WindowFunction::Aggregate(aggregate), Vec::new());
+            }
+            return Ok(Operand::Column(Column::Aggregate(aggregate)));
+        }
+        if let Some(function) = WindowFunction::from_name(&name) {
+            let args = self.parse_arguments()?;
+            return self.parse_over(function, args);
+        }
+
+        let Some(function) = ScalarFunction::from_name(&name) else {
+            return Err(CustomError::SyntaxError {
+                message: format!("unknown function '{}'", name),
+                line: name_token.line,
+                column: name_token.column,
+            });
+        };
+        let args = self.parse_arguments()?;
+        Ok(Operand::Function { function, args })
+    }
+
+    // The arguments of a call whose '(' was already read, up to and including ')'.
+    fn parse_arguments(&mut self) -> Result<Vec<Operand>, CustomError> {
+        let mut args = Vec::new();
+        if !self.consume(&TokenKind::RightParen) {
+            args.push(self.parse_operand()?);
+            while self.consume(&TokenKind::Comma) {
+                args.push(self.parse_operand()?);
+            }
+            self.expect(&TokenKind::RightParen, "')'")?;
+        }
+        Ok(args)
+    }
+
+    // OVER ([PARTITION BY operand, ...] [ORDER BY key, ...])
+    fn parse_over(
+        &mut self,
+        function: WindowFunction,
+        args: Vec<Operand>,
+    ) -> Result<Operand, CustomError> {
+        self.expect_keyword(Keyword::Over)?;
+        self.expect(&TokenKind::LeftParen, "'('")?;
+        let mut partition_by = Vec::new();
+        if self.consume_keyword(Keyword::Partition) {
+            self.expect_keyword(Keyword::By)?;
+            partition_by.push(self.parse_operand()?);
+            while self.consume(&TokenKind::Comma) {
+                partition_by.push(self.parse_operand()?);
+            }
+        }
+        let order_by = self.parse_order_by()?;
+        self.expect(&TokenKind::RightParen, "')'")?;
+        Ok(Operand::Window(Box::new(Window {
+            function,
+            args,
+            partition_by,
+            order_by,
+        })))
+    }
+
+    fn at_operator(&self) -> bool {
+        matches!(
+            self.peek().kind,
+            TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::Slash
+                | TokenKind::Eq
+                | TokenKind::Ne
+                | TokenKind::Lt
+                | TokenKind::Le
+                | TokenKind::Gt
+                | TokenKind::Ge
+                | TokenKind::Tilde
+                | TokenKind::Keyword(
+                    Keyword::Is
+                        | Keyword::Like
+                        | Keyword::ILike
+                        | Keyword::In
+                        | Keyword::Between
+                        | Keyword::Regexp
+                )
+        )
+    }
+
+    // `x NOT LIKE p`, `x NOT IN (...)` and `x NOT BETWEEN a AND b` become NOT around
+    // the positive comparison.
+    fn parse_comparison(&mut self) -> Result<Expr, CustomError> {
+        let left = self.parse_operand()?;
+        if self.consume_keyword(Keyword::Is) {
+            let op = if self.consume_keyword(Keyword::Not) {
+                Op::IsNotNull
+            } else {
+                Op::IsNull
+            };
+            self.expect_keyword(Keyword::Null)?;
+            return Ok(Expr::Comparison(Comparison {
+                left,
+                op,
+                values: Vec::new(),
+            }));
+        }
+
+        let negated = self.consume_keyword(Keyword::Not);
+        let op = match self.peek().kind {
+            TokenKind::Eq if !negated => Op::Eq,
+            TokenKind::Ne if !negated => Op::Ne,
+            TokenKind::Lt if !negated => Op::Lt,
+            TokenKind::Le if !negated => Op::Le,
+            TokenKind::Gt if !negated => Op::Gt,
+            TokenKind::Ge if !negated => Op::Ge,
+            TokenKind::Keyword(Keyword::Like) => Op::Like,
+            TokenKind::Keyword(Keyword::ILike) => Op::ILike,
+            TokenKind::Keyword(Keyword::In) => Op::In,
+            TokenKind::Keyword(Keyword::Between) => Op::Between,
+            TokenKind::Tilde | TokenKind::Keyword(Keyword::Regexp) => Op::Regexp,
+            _ if negated => return Err(self.unexpected("LIKE, ILIKE, IN, BETWEEN or REGEXP")),
+            _ => return Err(self.unexpected("comparison operator")),
+        };
+        self.advance();
+
+        let values = match op {
+            Op::In if self.starts_subquery() => {
+                vec![Operand::Subquery(Box::new(self.parse_subquery()?))]
+            }
+            Op::In => {
+                self.expect(&TokenKind::LeftParen, "'('")?;
+                let mut values = vec![self.parse_operand()?];
+                while self.consume(&TokenKind::Comma) {
+                    values.push(self.parse_operand()?);
+                }
+                self.expect(&TokenKind::RightParen, "')'")?;
+                values
+            }
+            Op::Between => {
+                let low = self.parse_operand()?;
+                self.expect_keyword(Keyword::And)?;
+                vec![low, self.parse_operand()?]
+            }
+            _ => vec![self.parse_operand()?],
+        };
+
+        let comparison = Expr::Comparison(Comparison { left, op, values });
+        if negated {
+            Ok(Expr::Not(Box::new(comparison)))
+        } else {
+            Ok(comparison)
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, CustomError> {
+        let negative = self.consume(&TokenKind::Minus);
+        let literal = match &self.peek().kind {
+            TokenKind::Number(n) if negative => Literal::Number(format!("-{}", n)),
+            TokenKind::Number(n) => Literal::Number(n.clone()),
+            TokenKind::String(s) if !negative => Literal::Quoted(s.clone()),
+            TokenKind::Parameter(n) if !negative => Literal::Parameter(*n),
+            _ => match self.name_at(0) {
+                Some(word) if !negative => Literal::Word(word),
+                _ => return Err(self.unexpected("value")),
+            },
+        };
+        self.advance();
+        Ok(literal)
+    }
+
+    // A literal where a field could not stand either, so a bare word other than TRUE or
+    // FALSE is a mistake rather than text, as it is in a WHERE condition.
+    fn parse_value(&mut self) -> Result<Literal, CustomError> {
+        if let Some(word) = self.name_at(0)
+            && !word.eq_ignore_ascii_case("true")
+            && !word.eq_ignore_ascii_case("false")
+        {
+            let token = self.peek();
+            return Err(CustomError::SyntaxError {
+                message: format!(
+                    "expected value, found '{}' (quote it to use it as text)",
+                    word
+                ),
+                line: token.line,
+                column: token.column,
+            });
+        }
+        self.parse_literal()
+    }
+
+    fn rest_of_input(&mut self) -> Result<String, CustomError> {
+        let start = self.peek().offset;
+        let rest = self.source[start..].trim();
+        if rest.is_empty() {
+            return Err(self.unexpected("file path"));
+        }
+        self.position = self.tokens.len() - 1;
+        Ok(rest.to_string())
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn peek_ahead(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn advance(&mut self) -> &Token {
+        let token = &self.tokens[self.position];
+        if self.position < self.tokens.len() - 1 {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn consume(&mut self, kind: &TokenKind) -> bool {
+        if &self.peek().kind == kind {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: Keyword) -> bool {
+        self.consume(&TokenKind::Keyword(keyword))
+    }
+
+    fn expect(&mut self, kind: &TokenKind, expected: &str) -> Result<(), CustomError> {
+        if self.consume(kind) {
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: Keyword) -> Result<(), CustomError> {
+        self.expect(&TokenKind::Keyword(keyword), keyword.as_str())
+    }
+
+    fn expect_identifier(&mut self, expected: &str) -> Result<String, CustomError> {
+        match self.name_at(0) {
+            Some(name) => {
+                self.advance();
+                Ok(name)
+            }
+            None => Err(self.unexpected(expected)),
+        }
+    }
+
+    // The name the token `offset` places ahead gives, as written: an identifier or a
+    // keyword that is not reserved.
+    fn name_at(&self, offset: usize) -> Option<String> {
+        let token = self.peek_ahead(offset)?;
+        match &token.kind {
+            TokenKind::Identifier(name) => Some(name.clone()),
+            TokenKind::Keyword(keyword) if !keyword.is_reserved() => {
+                let word = self.source[token.offset..]
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .next()
+                    .unwrap_or_default();
+                Some(word.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    // An optional keyword such as the TABLE in `TRUNCATE TABLE t` is only read as one
+    // when a name follows it; otherwise it is the name.
+    fn consume_keyword_before_name(&mut self, keyword: Keyword) -> bool {
+        self.peek().kind == TokenKind::Keyword(keyword)
+            && self.name_at(1).is_some()
+            && self.consume_keyword(keyword)
+    }
+
+    fn expect_count(&mut self) -> Result<usize, CustomError> {
+        if let TokenKind::Number(n) = &self.peek().kind
+            && let Ok(count) = n.parse::<usize>()
+        {
+            self.advance();
+            return Ok(count);
+        }
+        Err(self.unexpected("non-negative integer"))
+    }
+
+    fn unexpected(&self, expected: &str) -> CustomError {
+        let token = self.peek();
+        CustomError::SyntaxError {
+            message: format!("expected {}, found {}", expected, token.kind.describe()),
+            line: token.line,
+            column: token.column,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Column, Expr, InsertRows, Literal, Operand, Projection, Query, Statement},
+        custom_error::CustomError,
+    };
+
+    use super::parse;
+
+    fn syntax_error(input: &str) -> (usize, usize, String) {
+        match parse(input) {
+            Err(CustomError::SyntaxError {
+                message,
+                line,
+                column,
+            }) => (line, column, message),
+            other => panic!("expected a syntax error for {}, got {:?}", input, other),
+        }
+    }
+
+    fn query(input: &str) -> Query {
+        match parse(input) {
+            Ok(Statement::Select(query)) => query,
+            other => panic!("expected a query for {}, got {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn queries_parse_back_from_their_text() {
+        let inputs = [
+            "SELECT * FROM t",
+            "SELECT DISTINCT a AS b, t.* FROM t WHERE NOT (a = 1 OR b != 'x') AND c <= -2.5",
+            "SELECT a FROM t WHERE s LIKE 'a%' AND n IN (1, 2) AND m BETWEEN 1 AND 3",
+            "SELECT a FROM t WHERE s NOT ILIKE 'A' AND x IS NOT NULL AND s ~ '^\\d+$'",
+            "SELECT (a + 1) * 2 - b / 3, UPPER(s), ROUND(f, 1) FROM t ORDER BY a DESC, b LIMIT 5 OFFSET 2",
+            "SELECT g, COUNT(*), SUM(n) FROM t GROUP BY g HAVING COUNT(*) > 1",
+            "SELECT t.a, u.b FROM t LEFT JOIN u ON t.id = u.tid",
+            "SELECT a FROM t UNION ALL SELECT b FROM u INTERSECT SELECT c FROM v ORDER BY a",
+            "SELECT a FROM (SELECT a FROM t) AS d WHERE a IN (SELECT b FROM u) AND EXISTS (SELECT c FROM v)",
+            "WITH RECURSIVE c(n) AS (SELECT id FROM t UNION SELECT c.n FROM c) SELECT n FROM c",
+            "SELECT ROW_NUMBER() OVER (PARTITION BY g ORDER BY n DESC), LAG(n, 2, 0) OVER (ORDER BY n) FROM t",
+            "SELECT CASE s WHEN 'a' THEN 1 ELSE 2 END, CASE WHEN n > 1 THEN 'x\\'y' END FROM t",
+        ];
+        for input in inputs {
+            let parsed = query(input);
+            assert_eq!(query(&parsed.to_string()), parsed, "{}", input);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(
+            syntax_error("CREATE t KEY id FIELDS a Int"),
+            (1, 26, "expected ':', found 'Int'".to_string())
+        );
+        assert_eq!(
+            syntax_error("SELECT a FROM t\nWHERE a ="),
+            (2, 10, "expected field name, found end of input".to_string())
+        );
+        assert_eq!(
+            syntax_error("SELECT a FROM t ORDER BY a LIMIT 1 UNION SELECT b FROM u"),
+            (
+                1,
+                36,
+                "ORDER BY and LIMIT must follow the last SELECT".to_string()
+            )
+        );
+        assert!(matches!(
+            parse("SHOW t"),
+            Err(CustomError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn conditions_are_joined_by_and_or_or() {
+        let (line, column, _) = syntax_error("SELECT * FROM t WHERE id = 1, id = 2");
+        assert_eq!((line, column), (1, 29));
+        let condition = match query("SELECT * FROM t WHERE NOT a = 1 OR b = 2 AND (c = 3 OR d = 4)")
+        {
+            Query::Select(select) => select.condition.unwrap(),
+            other => panic!("expected a plain SELECT, got {}", other),
+        };
+        assert_eq!(
+            condition.to_string(),
+            "NOT a = 1 OR b = 2 AND (c = 3 OR d = 4)"
+        );
+        let Expr::Or(left, right) = condition else {
+            panic!("expected OR at the top, got {}", condition);
+        };
+        assert!(matches!(*left, Expr::Not(_)));
+        assert!(matches!(*right, Expr::And(..)));
+    }
+
+    #[test]
+    fn bare_words_are_text_only_in_the_legacy_insert() {
+        for input in [
+            "UPDATE t SET s = zzz",
+            "INSERT INTO t VALUES (1, zzz)",
+            "ALTER TABLE t ADD c: String DEFAULT zzz",
+            "EXECUTE q (zzz)",
+        ] {
+            let (_, _, message) = syntax_error(input);
+            assert!(
+                message.contains("'zzz' (quote it"),
+                "{}: {}",
+                input,
+                message
+            );
+        }
+        assert!(parse("UPDATE t SET b = TRUE, s = 'zzz'").is_ok());
+
+        let Ok(Statement::Insert(insert)) = parse("INSERT s = zzz, b = true INTO t") else {
+            panic!("expected INSERT");
+        };
+        let InsertRows::Values(rows) = insert.rows else {
+            panic!("expected VALUES");
+        };
+        assert_eq!(
+            rows,
+            [[
+                Some(Literal::Quoted("zzz".to_string())),
+                Some(Literal::Word("true".to_string()))
+            ]]
+        );
+    }
+
+    #[test]
+    fn unreserved_keywords_are_names() {
+        let statement =
+            parse("CREATE table KEY key FIELDS key: Int, left: String, end: Int").unwrap();
+        let Statement::Create(create) = statement else {
+            panic!("expected CREATE");
+        };
+        assert_eq!(create.table_name, "table");
+        assert_eq!(create.key_field, "key");
+        assert_eq!(create.fields[1], ("left".to_string(), "String".to_string()));
+
+        let Statement::Select(Query::Select(select)) =
+            parse("SELECT Set, to AS do FROM all WHERE end = 1").unwrap()
+        else {
+            panic!("expected SELECT");
+        };
+        assert_eq!(select.table_name, "all");
+        assert_eq!(
+            select.fields[0],
+            Projection::Operand {
+                operand: Operand::Column(Column::Field("Set".to_string())),
+                alias: None,
+            }
+        );
+        assert!(parse("UPDATE set SET to = 1 WHERE key = 2").is_ok());
+        assert!(parse("INSERT key = 1, do = all INTO table").is_ok());
+        assert!(parse("SELECT * FROM t LEFT JOIN left ON t.key = left.key").is_ok());
+    }
+
+    #[test]
+    fn optional_keywords_before_a_name() {
+        assert!(
+            matches!(parse("TRUNCATE table").unwrap(), Statement::Truncate(t) if t.table_name == "table")
+        );
+        assert!(matches!(parse("DROP TABLE if").unwrap(), Statement::Drop(d) if !d.if_exists));
+        assert!(
+            matches!(parse("DROP TABLE IF EXISTS t").unwrap(), Statement::Drop(d) if d.if_exists)
+        );
+    }
+
+    #[test]
+    fn null_is_an_operand() {
+        let input = "SELECT COALESCE(x, NULL), CASE WHEN x > 1 THEN \"a\" ELSE NULL END FROM t";
+        let Statement::Select(query) = parse(input).unwrap() else {
+            panic!("expected SELECT");
+        };
+        let Query::Select(select) = &query else {
+            panic!("expected a plain SELECT");
+        };
+        assert!(matches!(
+            &select.fields[0],
+            Projection::Operand { operand: Operand::Function { args, .. }, .. } if args[1] == Operand::Null
+        ));
+        assert_eq!(query.to_string(), input);
+    }
+
+    #[test]
+    fn reserved_keywords_are_not_names() {
+        assert_eq!(
+            syntax_error("SELECT FROM t"),
+            (1, 8, "expected field name, found FROM".to_string())
+        );
+        assert_eq!(
+            syntax_error("SELECT a\nFROM where"),
+            (2, 6, "expected table name, found WHERE".to_string())
+        );
+    }
+}
@@ -1,60 +1,196 @@
 use crate::{
-    condition::{Condition, Op},
+    ast::{Column, Comparison, Expr, Literal, Operand},
+    condition::{Condition, Op, Predicate},
     custom_error::CustomError,
+    database::Value,
+    expression::{Scalar, bind_operand, bind_typed, comparable, subquery_column, subquery_result},
 };
+use regex::Regex;
 use std::collections::HashMap;
-pub fn parse_fields(fields_str: &str) -> Result<HashMap<String, String>, CustomError> {
-    let mut fields: HashMap<String, String> = HashMap::new();
+pub fn parse_fields(fields: &[(String, String)]) -> Result<HashMap<String, String>, CustomError> {
+    let mut result: HashMap<String, String> = HashMap::new();
 
-    for part in fields_str.split(',') {
-        let trimmed = part.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-        let mut kv = trimmed.split(':');
-        if let (Some(name), Some(typ)) = (kv.next(), kv.next()) {
-            fields.insert(name.trim().to_string(), typ.trim().to_string());
-        } else {
-            return Err(CustomError::FieldParseError(trimmed.to_string()));
+    for (name, typ) in fields {
+        if result.insert(name.to_string(), typ.to_string()).is_some() {
+            return Err(CustomError::FieldParseError(name.to_string()));
         }
     }
 
-    Ok(fields)
+    Ok(result)
 }
-pub fn parse_conditions(
-    cond_str: &str,
+pub fn parse_condition(
+    comparison: &Comparison,
     fields: &HashMap<String, String>,
-) -> Result<Vec<Condition>, CustomError> {
-    cond_str
-        .split(',')
-        .map(|cond| {
-            let parts: Vec<&str> = cond.split_whitespace().collect();
-            if parts.len() != 3 {
-                return Err(CustomError::ConditionParseError(
-                    "Invalid condition format".to_string(),
-                ));
+) -> Result<Condition, CustomError> {
+    // Either side may name a field, so a bare word on the right has to be one: a
+    // misspelled column would otherwise quietly compare with its own name. Only TRUE
+    // and FALSE are read as values.
+    let operands = comparison
+        .values
+        .iter()
+        .map(|v| match v {
+            Operand::Column(Column::Field(name))
+                if !name.contains('.') && !fields.contains_key(name) =>
+            {
+                match name.eq_ignore_ascii_case("true") || name.eq_ignore_ascii_case("false") {
+                    true => Ok(Operand::Literal(Literal::Word(name.to_string()))),
+                    false => Err(CustomError::UnknownField(format!(
+                        "{} (quote it to compare with the text)",
+                        name
+                    ))),
+                }
             }
+            _ => Ok(v.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-            let field_name = parts[0];
-            let op_str = parts[1];
-            let value_str = parts[2];
+    // Literals take the type of the other side, so a literal on the left looks right first.
+    let hint = match &comparison.left {
+        Operand::Literal(_) => operands
+            .iter()
+            .find(|v| !matches!(v, Operand::Literal(_) | Operand::Subquery(_)))
+            .map(|v| bind_operand(v, fields))
+            .transpose()?
+            .map(|(_, typ)| typ),
+        _ => None,
+    };
+    let (left, left_type) = bind_typed(&comparison.left, fields, hint.as_deref())?;
 
-            let op = Op::as_str(op_str)
-                .ok_or_else(|| CustomError::ConditionParseError("Unknown operator".to_string()))?;
+    if matches!(comparison.op, Op::Like | Op::ILike | Op::Regexp) && left_type != "String" {
+        return Err(CustomError::ConditionParseError(format!(
+            "{} requires a String field, {} is {}",
+            comparison.op, comparison.left, left_type
+        )));
+    }
 
-            let field_type = fields
-                .get(field_name)
-                .ok_or_else(|| CustomError::ConditionParseError("Field not found".to_string()))?;
+    let mut values = Vec::new();
+    for operand in &operands {
+        // `x IN (SELECT ...)` compares with every value the subquery returned.
+        if let (Op::In, Operand::Subquery(subquery)) = (&comparison.op, operand) {
+            let (column, value_type) = subquery_column(subquery)?;
+            if !comparable(&value_type, &left_type) {
+                return Err(CustomError::TypeError(format!(
+                    "cannot compare {} ({}) with {} ({})",
+                    comparison.left, left_type, operand, value_type
+                )));
+            }
+            values.extend(column.into_iter().flatten().map(Scalar::Constant));
+            continue;
+        }
+        let (value, value_type) = bind_typed(operand, fields, Some(&left_type))?;
+        if !comparable(&value_type, &left_type) {
+            return Err(CustomError::TypeError(format!(
+                "cannot compare {} ({}) with {} ({})",
+                comparison.left, left_type, operand, value_type
+            )));
+        }
+        values.push(value);
+    }
 
-            Condition::new(field_name, op, value_str, field_type).map_err(|_| {
-                CustomError::ConditionParseError("Failed to parse value for condition".to_string())
-            })
-        })
-        .collect::<Result<Vec<Condition>, CustomError>>()
+    // Compiled here, once per statement, instead of for every record it is matched against.
+    let regex = match (&comparison.op, values.first()) {
+        (Op::Regexp, Some(Scalar::Constant(Value::String(pattern)))) => {
+            Some(Regex::new(pattern).map_err(|e| {
+                // The regex crate points at the error on separate lines; keep only the reason.
+                let message = e.to_string();
+                let reason = message.lines().last().unwrap_or_default();
+                CustomError::InvalidPattern(
+                    pattern.to_string(),
+                    reason.trim_start_matches("error: ").to_string(),
+                )
+            })?)
+        }
+        (Op::Regexp, _) => {
+            return Err(CustomError::ConditionParseError(format!(
+                "{} requires a constant pattern",
+                comparison.op
+            )));
+        }
+        _ => None,
+    };
+    Ok(Condition {
+        left,
+        op: comparison.op.clone(),
+        values,
+        regex,
+    })
 }
-pub fn parse_fields_list(fields_str: &str) -> Vec<String> {
-    fields_str
-        .split(',')
-        .map(|a| a.trim().to_string())
-        .collect()
+pub fn parse_predicate(
+    expr: &Expr,
+    fields: &HashMap<String, String>,
+) -> Result<Predicate, CustomError> {
+    let predicate = match expr {
+        Expr::Comparison(c) => Predicate::Condition(parse_condition(c, fields)?),
+        Expr::And(l, r) => Predicate::And(
+            Box::new(parse_predicate(l, fields)?),
+            Box::new(parse_predicate(r, fields)?),
+        ),
+        Expr::Or(l, r) => Predicate::Or(
+            Box::new(parse_predicate(l, fields)?),
+            Box::new(parse_predicate(r, fields)?),
+        ),
+        Expr::Not(e) => Predicate::Not(Box::new(parse_predicate(e, fields)?)),
+        Expr::Exists(s) => Predicate::Constant(!subquery_result(s)?.rows.is_empty()),
+    };
+    Ok(predicate)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        ast::{Column, Comparison, Operand},
+        condition::Op,
+        custom_error::CustomError,
+        database::{Record, Value},
+    };
+
+    use super::parse_condition;
+
+    fn field(name: &str) -> Operand {
+        Operand::Column(Column::Field(name.to_string()))
+    }
+
+    fn fields() -> HashMap<String, String> {
+        HashMap::from([
+            ("s".to_string(), "String".to_string()),
+            ("t".to_string(), "String".to_string()),
+            ("ok".to_string(), "Bool".to_string()),
+        ])
+    }
+
+    fn compare(left: &str, right: &str) -> Comparison {
+        Comparison {
+            left: field(left),
+            op: Op::Eq,
+            values: vec![field(right)],
+        }
+    }
+
+    #[test]
+    fn unknown_right_hand_field_is_an_error() {
+        let result = parse_condition(&compare("s", "tpyo"), &fields());
+        assert!(matches!(result, Err(CustomError::UnknownField(_))));
+    }
+
+    #[test]
+    fn right_hand_field_is_compared_with_the_left() {
+        let condition = parse_condition(&compare("s", "t"), &fields()).unwrap();
+        let record = |t: &str| {
+            Record::with_values(HashMap::from([
+                ("s".to_string(), Value::String("x".to_string())),
+                ("t".to_string(), Value::String(t.to_string())),
+            ]))
+        };
+        assert!(condition.matches(&record("x")));
+        assert!(!condition.matches(&record("y")));
+    }
+
+    #[test]
+    fn true_and_false_are_values() {
+        let condition = parse_condition(&compare("ok", "TRUE"), &fields()).unwrap();
+        let record = Record::with_values(HashMap::from([("ok".to_string(), Value::Bool(true))]));
+        assert!(condition.matches(&record));
+    }
 }
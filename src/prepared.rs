@@ -0,0 +1,400 @@
+use std::{collections::HashMap, iter};
+
+use crate::{
+    ast::{Column, Expr, InsertRows, Literal, OnConflict, Operand, Query, Statement},
+    custom_error::CustomError,
+    database::{Database, DatabaseKey, Value},
+    expression::canonical_type,
+    parser::parse,
+};
+
+// A statement parsed once and run with different values for its `$1`, `$2`, ...
+// parameters. The values replace the parameters in the parsed statement, so they are
+// never spliced into SQL text.
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    statement: Statement,
+    // Taken from the field each parameter is compared with or stored in; None when the
+    // statement does not tie it to one.
+    types: Vec<Option<String>>,
+}
+
+pub fn prepare<K: DatabaseKey>(
+    database: &Database<K>,
+    input: &str,
+) -> Result<PreparedStatement, CustomError> {
+    PreparedStatement::new(database, parse(input.trim())?)
+}
+
+impl PreparedStatement {
+    pub fn new<K: DatabaseKey>(
+        database: &Database<K>,
+        mut statement: Statement,
+    ) -> Result<Self, CustomError> {
+        if !matches!(
+            statement,
+            Statement::Select(_)
+                | Statement::Insert(_)
+                | Statement::Update(_)
+                | Statement::Delete(_)
+        ) {
+            return Err(CustomError::ParameterError(
+                "only SELECT, INSERT, UPDATE and DELETE can be prepared".to_string(),
+            ));
+        }
+
+        let mut count = 0;
+        statement.for_each_literal(&mut |literal| {
+            if let Literal::Parameter(n) = literal {
+                count = count.max(*n);
+            }
+            Ok(())
+        })?;
+        let mut types = vec![None; count];
+        infer_statement(&statement, database, &mut types)?;
+        Ok(Self { statement, types })
+    }
+
+    pub fn parameter_types(&self) -> &[Option<String>] {
+        &self.types
+    }
+
+    // The statement with `$n` replaced by `values[n - 1]`, for `handle_statement`.
+    pub fn bind(&self, values: &[Value]) -> Result<Statement, CustomError> {
+        if values.len() != self.types.len() {
+            return Err(CustomError::ParameterError(format!(
+                "{} values given for {} parameters",
+                values.len(),
+                self.types.len()
+            )));
+        }
+        for (n, (value, typ)) in iter::zip(1.., iter::zip(values, &self.types)) {
+            let Some(typ) = typ else {
+                continue;
+            };
+            if value.type_name() != typ && !(value.type_name() == "Int" && typ == "Float") {
+                return Err(CustomError::TypeError(format!(
+                    "${} is {}, {} is {}",
+                    n,
+                    typ,
+                    value.as_string(),
+                    value.type_name()
+                )));
+            }
+        }
+
+        if let Some(n) = values
+            .iter()
+            .position(|v| matches!(v, Value::Float(f) if !f.is_finite()))
+        {
+            return Err(CustomError::ParameterError(format!(
+                "${} is {}, which has no literal",
+                n + 1,
+                values[n].as_string()
+            )));
+        }
+
+        let mut statement = self.statement.clone();
+        statement.for_each_literal(&mut |literal| {
+            if let Literal::Parameter(n) = literal {
+                *literal = match &values[*n - 1] {
+                    Value::Int(i) => Literal::Number(i.to_string()),
+                    Value::Float(f) => Literal::Number(float_literal(*f)),
+                    Value::String(s) => Literal::Quoted(s.to_string()),
+                    Value::Bool(b) => Literal::Word(b.to_string()),
+                };
+            }
+            Ok(())
+        })?;
+        Ok(statement)
+    }
+
+    // EXECUTE reads each argument as the type of its parameter, the way the literal
+    // would be read in its place, so `EXECUTE q('5')` does not fill an Int parameter.
+    pub fn bind_literals(&self, arguments: &[Literal]) -> Result<Statement, CustomError> {
+        let types = self.types.iter().chain(iter::repeat(&None));
+        let values = iter::zip(1.., iter::zip(arguments, types))
+            .map(|(n, (literal, typ))| {
+                let typ = match (typ, literal) {
+                    (Some(typ), _) => typ.as_str(),
+                    (None, Literal::Number(s)) if s.contains('.') => "Float",
+                    (None, Literal::Number(_)) => "Int",
+                    (None, Literal::Word(w))
+                        if w.eq_ignore_ascii_case("true") || w.eq_ignore_ascii_case("false") =>
+                    {
+                        "Bool"
+                    }
+                    (None, _) => "String",
+                };
+                Value::from_literal(literal, typ).ok_or_else(|| {
+                    CustomError::TypeError(format!("${} is {}, {} is not", n, typ, literal))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bind(&values)
+    }
+}
+
+// A parameter used in several places has to fit all of them.
+fn note(types: &mut [Option<String>], n: usize, typ: Option<&String>) -> Result<(), CustomError> {
+    let Some(typ) = typ.map(|t| canonical_type(t)) else {
+        return Ok(());
+    };
+    match &types[n - 1] {
+        None => types[n - 1] = Some(typ),
+        Some(known) if *known == typ => {}
+        Some(known) => {
+            return Err(CustomError::ParameterError(format!(
+                "${} is used as both {} and {}",
+                n, known, typ
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn parameter(literal: Option<&Literal>) -> Option<usize> {
+    match literal {
+        Some(Literal::Parameter(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn operand_parameter(operand: &Operand) -> Option<usize> {
+    match operand {
+        Operand::Literal(literal) => parameter(Some(literal)),
+        _ => None,
+    }
+}
+
+fn infer_statement<K: DatabaseKey>(
+    statement: &Statement,
+    database: &Database<K>,
+    types: &mut [Option<String>],
+) -> Result<(), CustomError> {
+    match statement {
+        Statement::Select(query) => infer_query(query, database, types),
+        Statement::Insert(insert) => {
+            let table = database.get_table(&insert.table_name)?;
+            let columns = match insert.columns.is_empty() {
+                true => &table.columns,
+                false => &insert.columns,
+            };
+            match &insert.rows {
+                InsertRows::Values(rows) => {
+                    for row in rows {
+                        for (column, value) in columns.iter().zip(row) {
+                            if let Some(n) = parameter(value.as_ref()) {
+                                note(types, n, table.fields.get(column))?;
+                            }
+                        }
+                    }
+                }
+                InsertRows::Query(query) => infer_query(query, database, types)?,
+            }
+            if let OnConflict::Update(assignments) = &insert.on_conflict {
+                for (field, operand) in assignments {
+                    if let Some(n) = operand_parameter(operand) {
+                        note(types, n, table.fields.get(field))?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Statement::Update(update) => {
+            let table = database.get_table(&update.table_name)?;
+            for assignment in &update.assignments {
+                if let Some(n) = parameter(Some(&assignment.value)) {
+                    note(types, n, table.fields.get(&assignment.field))?;
+                }
+            }
+            match &update.condition {
+                Some(condition) => infer_expr(condition, &table.fields, database, types),
+                None => Ok(()),
+            }
+        }
+        Statement::Delete(delete) => {
+            let table = database.get_table(&delete.table_name)?;
+            if let Some(n) = parameter(delete.key.as_ref()) {
+                note(types, n, table.fields.get(&table.key_field))?;
+            }
+            match &delete.condition {
+                Some(condition) => infer_expr(condition, &table.fields, database, types),
+                None => Ok(()),
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn infer_query<K: DatabaseKey>(
+    query: &Query,
+    database: &Database<K>,
+    types: &mut [Option<String>],
+) -> Result<(), CustomError> {
+    match query {
+        Query::Select(select) => {
+            // Fields go by their own name and by `table.field`, as in a join.
+            let mut fields = HashMap::new();
+            let from = match &select.derived {
+                Some(derived) => {
+                    infer_query(derived, database, types)?;
+                    None
+                }
+                None => Some(&select.table_name),
+            };
+            for name in from
+                .into_iter()
+                .chain(select.joins.iter().map(|j| &j.table_name))
+            {
+                if let Ok(table) = database.get_table(name) {
+                    for (field, typ) in &table.fields {
+                        fields.entry(field.to_string()).or_insert(typ.to_string());
+                        fields.insert(format!("{}.{}", name, field), typ.to_string());
+                    }
+                }
+            }
+            for expr in select.condition.iter().chain(&select.having) {
+                infer_expr(expr, &fields, database, types)?;
+            }
+            Ok(())
+        }
+        Query::Compound(compound) => {
+            infer_query(&compound.left, database, types)?;
+            infer_query(&compound.right, database, types)
+        }
+        Query::With(with) => {
+            for cte in &with.ctes {
+                infer_query(&cte.query, database, types)?;
+            }
+            infer_query(&with.body, database, types)
+        }
+    }
+}
+
+// `field op $n`, `$n op field` and `field IN ($1, $2)` give the parameter the field's type.
+fn infer_expr<K: DatabaseKey>(
+    expr: &Expr,
+    fields: &HashMap<String, String>,
+    database: &Database<K>,
+    types: &mut [Option<String>],
+) -> Result<(), CustomError> {
+    match expr {
+        Expr::Comparison(comparison) => {
+            let field_type = |operand: &Operand| match operand {
+                Operand::Column(Column::Field(name)) => fields.get(name),
+                _ => None,
+            };
+            match operand_parameter(&comparison.left) {
+                Some(n) => note(types, n, comparison.values.iter().find_map(field_type))?,
+                None => {
+                    let typ = field_type(&comparison.left);
+                    for n in comparison.values.iter().filter_map(operand_parameter) {
+                        note(types, n, typ)?;
+                    }
+                }
+            }
+            for value in &comparison.values {
+                if let Operand::Subquery(subquery) = value {
+                    infer_query(&subquery.query, database, types)?;
+                }
+            }
+            Ok(())
+        }
+        Expr::And(l, r) | Expr::Or(l, r) => {
+            infer_expr(l, fields, database, types)?;
+            infer_expr(r, fields, database, types)
+        }
+        Expr::Not(e) => infer_expr(e, fields, database, types),
+        Expr::Exists(subquery) => infer_query(&subquery.query, database, types),
+    }
+}
+
+// Display never uses an exponent, which the lexer would not read as one number; the
+// fraction is kept so that a whole number like 2.0 stays a Float.
+fn float_literal(f: f64) -> String {
+    let text = f.to_string();
+    match text.contains('.') {
+        true => text,
+        false => format!("{}.0", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command_history::CommandHistory,
+        custom_error::CustomError,
+        database::Value,
+        handlers::handle_statement,
+        test_util::{database, replay, run, run_logged},
+    };
+
+    use super::prepare;
+
+    #[test]
+    fn bound_floats_replay_from_history() {
+        let mut database = database(&[]);
+        let mut history = CommandHistory::new();
+        let create = "CREATE t KEY id FIELDS id: Int, f: Float";
+        run_logged(&mut database, &mut history, create).unwrap();
+        let insert = prepare(&database, "INSERT INTO t VALUES ($1, $2)").unwrap();
+        let floats = [1e20, 2.0, 0.1, -1.5, 1e-7, f64::MAX];
+        for (id, f) in (1..).zip(floats) {
+            let statement = insert.bind(&[Value::Int(id), Value::Float(f)]).unwrap();
+            handle_statement(statement, &mut database, &mut history).unwrap();
+        }
+        for statement in [
+            "PREPARE q AS INSERT INTO t VALUES ($1, $2)",
+            "EXECUTE q (7, 100000000000000000000.0)",
+        ] {
+            run_logged(&mut database, &mut history, statement).unwrap();
+        }
+
+        let mut replayed = replay(&history);
+        let select = "SELECT * FROM t";
+        let expected = run(&mut database, select).unwrap();
+        assert_eq!(run(&mut replayed, select).unwrap(), expected);
+        for id in 1..=7 {
+            let f = match id {
+                7 => 1e20,
+                _ => floats[id - 1],
+            };
+            let query = format!("SELECT id FROM t WHERE id = {} AND f = $1", id);
+            let statement = prepare(&replayed, &query)
+                .unwrap()
+                .bind(&[Value::Float(f)])
+                .unwrap();
+            let result = handle_statement(statement, &mut replayed, &mut CommandHistory::new());
+            assert_eq!(result.unwrap(), format!("id={}", id));
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_are_rejected() {
+        let database = database(&["CREATE t KEY id FIELDS id: Int, f: Float"]);
+        let insert = prepare(&database, "INSERT INTO t VALUES ($1, $2)").unwrap();
+        for f in [f64::NAN, f64::INFINITY] {
+            let result = insert.bind(&[Value::Int(1), Value::Float(f)]);
+            assert!(matches!(result, Err(CustomError::ParameterError(_))));
+        }
+    }
+
+    #[test]
+    fn bind_checks_count_and_types() {
+        let mut database = database(&["CREATE t KEY id FIELDS id: Int, s: String"]);
+        let select = prepare(&database, "SELECT * FROM t WHERE id = $1 AND s = $2").unwrap();
+        assert_eq!(
+            select.parameter_types(),
+            [Some("Int".to_string()), Some("String".to_string())]
+        );
+        assert!(select.bind(&[Value::Int(1)]).is_err());
+        let result = select.bind(&[Value::String("1".to_string()), Value::Int(1)]);
+        assert!(matches!(result, Err(CustomError::TypeError(_))));
+        let statement = select
+            .bind(&[Value::Int(1), Value::String("x' OR 'a' = 'a".to_string())])
+            .unwrap();
+        let result = handle_statement(statement, &mut database, &mut CommandHistory::new());
+        assert_eq!(result.unwrap(), "");
+    }
+}
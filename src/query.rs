@@ -0,0 +1,914 @@
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use crate::{
+    aggregate::group_records,
+    ast::{
+        CommonTableExpression, CompoundQuery, Expr, OrderBy, Query, SelectStatement, SetOperator,
+        Subquery, SubqueryResult, WithQuery,
+    },
+    condition::Predicate,
+    custom_error::CustomError,
+    database::{Database, DatabaseKey, Record, Value},
+    expression::{bind_operand, comparable},
+    join::select_joined,
+    parsers::parse_predicate,
+    window::apply_windows,
+};
+
+pub fn bind_condition(
+    condition: &Option<Expr>,
+    fields: &HashMap<String, String>,
+) -> Result<Option<Predicate>, CustomError> {
+    match condition {
+        Some(expr) => Ok(Some(parse_predicate(expr, fields)?)),
+        None => Ok(None),
+    }
+}
+
+pub struct ResultSet {
+    pub columns: Vec<String>,
+    pub types: Vec<String>,
+    pub records: Vec<Record>,
+}
+
+type Row = Vec<Option<Value>>;
+
+// The steps of a select, each reported to a trace with the number of rows it passed
+// on: EXPLAIN ANALYZE times them, everything else ignores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Source,
+    Filter,
+    Aggregate,
+    Window,
+    Sort,
+    Output,
+}
+
+// Results of the CTEs visible to a query, by name.
+pub type Scope = HashMap<String, Rc<ResultSet>>;
+
+// A recursive CTE that keeps producing rows is stopped after this many rounds.
+const MAX_RECURSION: usize = 1000;
+
+impl ResultSet {
+    // Projects, deduplicates for DISTINCT and pages the already ordered records lazily,
+    // so a LIMIT stops pulling from `records` once it is satisfied.
+    pub fn new<'a>(
+        statement: &SelectStatement,
+        fields: &HashMap<String, String>,
+        records: impl Iterator<Item = &'a Record>,
+    ) -> Result<Self, CustomError> {
+        let mut columns = Vec::new();
+        let mut types = Vec::new();
+        let mut scalars = Vec::new();
+        for (operand, name) in statement.output_columns() {
+            let (scalar, typ) = bind_operand(operand, fields)?;
+            columns.push(name);
+            types.push(typ);
+            scalars.push(scalar);
+        }
+        let mut seen = HashSet::new();
+        let records = records
+            .map(|r| {
+                Record::with_values(
+                    columns
+                        .iter()
+                        .zip(&scalars)
+                        .filter_map(|(name, s)| s.evaluate(r).map(|v| (name.to_string(), v)))
+                        .collect(),
+                )
+            })
+            .filter(|r| !statement.distinct || seen.insert(row_of(&columns, r)))
+            .skip(statement.offset)
+            .take(statement.limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok(Self {
+            columns,
+            types,
+            records,
+        })
+    }
+
+    pub fn into_subquery_result(self) -> SubqueryResult {
+        SubqueryResult {
+            rows: self.rows(),
+            types: self.types,
+        }
+    }
+
+    fn from_rows(columns: Vec<String>, types: Vec<String>, rows: Vec<Row>) -> Self {
+        let records = rows
+            .into_iter()
+            .map(|row| {
+                Record::with_values(
+                    columns
+                        .iter()
+                        .zip(row)
+                        .filter_map(|(c, v)| v.map(|v| (c.to_string(), v)))
+                        .collect(),
+                )
+            })
+            .collect();
+        Self {
+            columns,
+            types,
+            records,
+        }
+    }
+
+    // Column names mapped to their types, the way a table describes its fields.
+    pub fn schema(&self) -> HashMap<String, String> {
+        self.columns
+            .iter()
+            .cloned()
+            .zip(self.types.iter().cloned())
+            .collect()
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        self.records
+            .iter()
+            .map(|r| row_of(&self.columns, r))
+            .collect()
+    }
+
+    // Columns are matched by position; the result is named after the left side.
+    pub fn combine(self, compound: &CompoundQuery, other: ResultSet) -> Result<Self, CustomError> {
+        let types = column_types(compound.operator.as_str(), &self, &other)?;
+        let left = widen(self.rows(), &types);
+        let right = widen(other.rows(), &types);
+        let mut counts: HashMap<&Row, usize> = HashMap::new();
+        for row in &right {
+            *counts.entry(row).or_default() += 1;
+        }
+        let mut rows = match compound.operator {
+            SetOperator::Union => left.into_iter().chain(right.iter().cloned()).collect(),
+            SetOperator::Intersect => left
+                .into_iter()
+                .filter(|row| match counts.get_mut(row) {
+                    Some(n) if *n > 0 => {
+                        *n -= 1;
+                        true
+                    }
+                    _ => false,
+                })
+                .collect(),
+            SetOperator::Except if compound.all => left
+                .into_iter()
+                .filter(|row| match counts.get_mut(row) {
+                    Some(n) if *n > 0 => {
+                        *n -= 1;
+                        false
+                    }
+                    _ => true,
+                })
+                .collect(),
+            SetOperator::Except => left
+                .into_iter()
+                .filter(|row| !counts.contains_key(row))
+                .collect::<Vec<_>>(),
+        };
+        if !compound.all {
+            let mut seen = HashSet::new();
+            rows.retain(|row| seen.insert(row.clone()));
+        }
+        Ok(Self::from_rows(self.columns, types, rows))
+    }
+
+    // `WITH t(a, b) AS (...)` renames the columns of the query's result by position.
+    pub fn named(self, cte: &CommonTableExpression) -> Result<Self, CustomError> {
+        if cte.columns.is_empty() {
+            return Ok(self);
+        }
+        if cte.columns.len() != self.columns.len() {
+            return Err(CustomError::SubqueryError(format!(
+                "{} names {} columns but its query returns {}",
+                cte.name,
+                cte.columns.len(),
+                self.columns.len()
+            )));
+        }
+        let rows = self.rows();
+        Ok(Self::from_rows(cte.columns.clone(), self.types, rows))
+    }
+
+    pub fn sort_and_page(
+        mut self,
+        order_by: &[OrderBy],
+        limit: Option<usize>,
+        offset: usize,
+    ) -> Result<Self, CustomError> {
+        let fields = self.schema();
+        self.records = sort_records(self.records, order_by, &fields)?
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        Ok(self)
+    }
+}
+
+// Evaluates the ORDER BY operands once per record; NULLs sort first.
+fn sort_records<R: Borrow<Record>>(
+    records: Vec<R>,
+    order_by: &[OrderBy],
+    fields: &HashMap<String, String>,
+) -> Result<Vec<R>, CustomError> {
+    if order_by.is_empty() {
+        return Ok(records);
+    }
+    let keys = order_by
+        .iter()
+        .map(|k| Ok((bind_operand(&k.operand, fields)?.0, k.descending)))
+        .collect::<Result<Vec<_>, CustomError>>()?;
+    let mut keyed = records
+        .into_iter()
+        .map(|r| {
+            let row = keys
+                .iter()
+                .map(|(scalar, _)| scalar.evaluate(r.borrow()))
+                .collect::<Row>();
+            (row, r)
+        })
+        .collect::<Vec<_>>();
+    let descending = keys.iter().map(|(_, d)| *d).collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| compare_keys(a, b, &descending));
+    Ok(keyed.into_iter().map(|(_, r)| r).collect())
+}
+
+// Compares evaluated sort keys in order, each one reversed where `descending` says so.
+pub fn compare_keys(a: &[Option<Value>], b: &[Option<Value>], descending: &[bool]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .zip(descending)
+        .map(|((a, b), descending)| match descending {
+            true => b.cmp(a),
+            false => a.cmp(b),
+        })
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+// The types of a set operation's columns: both sides have to agree, except that an Int
+// column meeting a Float one becomes Float.
+fn column_types(
+    operator: &str,
+    left: &ResultSet,
+    right: &ResultSet,
+) -> Result<Vec<String>, CustomError> {
+    if left.columns.len() != right.columns.len() {
+        return Err(CustomError::SetOperationError(format!(
+            "{} needs the same number of columns on both sides, found {} and {}",
+            operator,
+            left.columns.len(),
+            right.columns.len()
+        )));
+    }
+    left.types
+        .iter()
+        .zip(&right.types)
+        .zip(&left.columns)
+        .map(|((l, r), column)| match comparable(l, r) {
+            true if l == r || r == "Null" => Ok(l.to_string()),
+            true if l == "Null" => Ok(r.to_string()),
+            true => Ok("Float".to_string()),
+            false => Err(CustomError::SetOperationError(format!(
+                "{} column {} is {} on one side and {} on the other",
+                operator, column, l, r
+            ))),
+        })
+        .collect()
+}
+
+fn widen(mut rows: Vec<Row>, types: &[String]) -> Vec<Row> {
+    for row in &mut rows {
+        for (value, typ) in row.iter_mut().zip(types) {
+            if let Some(Value::Int(i)) = value
+                && typ == "Float"
+            {
+                *value = Some(Value::Float(*i as f64));
+            }
+        }
+    }
+    rows
+}
+
+fn row_of(columns: &[String], record: &Record) -> Row {
+    columns
+        .iter()
+        .map(|c| record.values.get(c).cloned())
+        .collect()
+}
+
+pub fn execute_query<K: DatabaseKey>(
+    database: &mut Database<K>,
+    query: &Query,
+) -> Result<ResultSet, CustomError> {
+    execute_in(database, &Scope::new(), query)
+}
+
+// Runs `query` with the CTEs of `scope` readable as tables.
+pub fn execute_in<K: DatabaseKey>(
+    database: &mut Database<K>,
+    scope: &Scope,
+    query: &Query,
+) -> Result<ResultSet, CustomError> {
+    match query {
+        Query::Select(select) => {
+            let mut select = select.as_ref().clone();
+            select
+                .for_each_subquery(&mut |subquery| resolve_subquery(database, scope, subquery))?;
+            if !select.joins.is_empty() {
+                return select_joined(database, scope, &select, &mut |_, _| {});
+            }
+            let rows = match &select.derived {
+                Some(derived) => Some(Rc::new(execute_in(database, scope, derived)?)),
+                None => scope.get(&select.table_name).cloned(),
+            };
+            match rows {
+                Some(result) => {
+                    let statement = scope_to(&select, &result.columns)?;
+                    select_rows(
+                        result.records.iter().collect(),
+                        &result.schema(),
+                        &statement,
+                        &mut |_, _| {},
+                    )
+                }
+                None => database
+                    .get_table(&select.table_name)?
+                    .select_records(&select, &mut |_, _| {}),
+            }
+        }
+        Query::Compound(compound) => {
+            let left = execute_in(database, scope, &compound.left)?;
+            let right = execute_in(database, scope, &compound.right)?;
+            left.combine(compound, right)?.sort_and_page(
+                &compound.order_by,
+                compound.limit,
+                compound.offset,
+            )
+        }
+        Query::With(with) => execute_with(database, scope, with),
+    }
+}
+
+fn execute_with<K: DatabaseKey>(
+    database: &mut Database<K>,
+    scope: &Scope,
+    with: &WithQuery,
+) -> Result<ResultSet, CustomError> {
+    let mut scope = scope.clone();
+    for cte in &with.ctes {
+        let result = match recursive_union(with, cte) {
+            Some(compound) => execute_recursive(database, &scope, cte, compound)?,
+            None => execute_in(database, &scope, &cte.query)?.named(cte)?,
+        };
+        scope.insert(cte.name.to_string(), Rc::new(result));
+    }
+    execute_in(database, &scope, &with.body)
+}
+
+// The UNION of a CTE under WITH RECURSIVE whose right side reads the CTE itself.
+pub fn recursive_union<'q>(
+    with: &WithQuery,
+    cte: &'q CommonTableExpression,
+) -> Option<&'q CompoundQuery> {
+    match &cte.query {
+        Query::Compound(compound)
+            if with.recursive
+                && compound.operator == SetOperator::Union
+                && compound.right.references(&cte.name) =>
+        {
+            Some(compound)
+        }
+        _ => None,
+    }
+}
+
+// Runs the anchor once, then the recursive part against only the rows the previous
+// round added, until a round adds none. UNION without ALL drops rows already seen.
+pub fn execute_recursive<K: DatabaseKey>(
+    database: &mut Database<K>,
+    scope: &Scope,
+    cte: &CommonTableExpression,
+    compound: &CompoundQuery,
+) -> Result<ResultSet, CustomError> {
+    let anchor = execute_in(database, scope, &compound.left)?.named(cte)?;
+    let mut seen = HashSet::new();
+    let mut rows = anchor.rows();
+    if !compound.all {
+        rows.retain(|row| seen.insert(row.clone()));
+    }
+
+    let mut scope = scope.clone();
+    let mut added = rows.clone();
+    let mut rounds = 0;
+    while !added.is_empty() {
+        if rounds == MAX_RECURSION {
+            return Err(CustomError::SubqueryError(format!(
+                "{} did not finish within {} rounds",
+                cte.name, MAX_RECURSION
+            )));
+        }
+        rounds += 1;
+        let previous = ResultSet::from_rows(anchor.columns.clone(), anchor.types.clone(), added);
+        scope.insert(cte.name.to_string(), Rc::new(previous));
+        let step = execute_in(database, &scope, &compound.right)?;
+        // Earlier rounds already hold the anchor's types, so only an Int may widen.
+        let types = column_types("UNION", &anchor, &step)?;
+        if types != anchor.types {
+            return Err(CustomError::SetOperationError(format!(
+                "the recursive part of {} returns {} where the anchor returns {}",
+                cte.name,
+                step.types.join(", "),
+                anchor.types.join(", ")
+            )));
+        }
+        added = widen(step.rows(), &types);
+        if !compound.all {
+            added.retain(|row| seen.insert(row.clone()));
+        }
+        rows.extend(added.iter().cloned());
+    }
+    ResultSet::from_rows(anchor.columns, anchor.types, rows).sort_and_page(
+        &compound.order_by,
+        compound.limit,
+        compound.offset,
+    )
+}
+
+pub fn run_subquery<K: DatabaseKey>(
+    database: &mut Database<K>,
+    subquery: &mut Subquery,
+) -> Result<(), CustomError> {
+    resolve_subquery(database, &Scope::new(), subquery)
+}
+
+fn resolve_subquery<K: DatabaseKey>(
+    database: &mut Database<K>,
+    scope: &Scope,
+    subquery: &mut Subquery,
+) -> Result<(), CustomError> {
+    let result = execute_in(database, scope, &subquery.query)?;
+    subquery.result = Some(result.into_subquery_result());
+    Ok(())
+}
+
+// Expands wildcards to `columns` of the single FROM source and accepts both `field`
+// and `table.field` for them.
+pub fn scope_to(
+    statement: &SelectStatement,
+    columns: &[String],
+) -> Result<SelectStatement, CustomError> {
+    let table_name = &statement.table_name;
+    statement
+        .expand_wildcards(&|table| match table {
+            Some(t) if t != table_name => Err(CustomError::TableNotFound(t.to_string())),
+            _ => Ok(columns.to_vec()),
+        })?
+        .map_fields(&|name| match name.split_once('.') {
+            Some((table, field)) if table == table_name => Ok(field.to_string()),
+            Some(_) => Err(CustomError::UnknownField(name.to_string())),
+            None => Ok(name.to_string()),
+        })
+}
+
+// Every column is shown, a missing value as NULL, so a row of NULLs is still a row.
+impl Display for ResultSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rows = self
+            .records
+            .iter()
+            .map(|r| {
+                self.columns
+                    .iter()
+                    .map(|c| match r.values.get(c) {
+                        Some(v) => format!("{}={}", c, v.as_string()),
+                        None => format!("{}=NULL", c),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", rows.join(" | "))
+    }
+}
+
+// Runs WHERE, GROUP BY/HAVING, ORDER BY, LIMIT/OFFSET and the projection over an
+// already materialized row set whose columns are described by `fields`, calling
+// `trace` after each stage.
+pub fn select_rows(
+    rows: Vec<&Record>,
+    fields: &HashMap<String, String>,
+    statement: &SelectStatement,
+    trace: &mut impl FnMut(Stage, usize),
+) -> Result<ResultSet, CustomError> {
+    let cond = bind_condition(&statement.condition, fields)?;
+    let matched = rows
+        .into_iter()
+        .filter(|r| match &cond {
+            Some(predicate) => predicate.matches(r),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    trace(Stage::Filter, matched.len());
+
+    let windows = statement.windows();
+    let result = if statement.is_grouped() {
+        let (mut rows, mut schema) = group_rows(matched, fields, statement)?;
+        trace(Stage::Aggregate, rows.len());
+        apply_windows(&mut rows, &mut schema, &windows)?;
+        trace(Stage::Window, rows.len());
+        let rows = sort_records(rows, &statement.order_by, &schema)?;
+        trace(Stage::Sort, rows.len());
+        ResultSet::new(statement, &schema, rows.iter())?
+    } else if !windows.is_empty() {
+        let mut rows = matched.into_iter().cloned().collect::<Vec<_>>();
+        let mut schema = fields.clone();
+        apply_windows(&mut rows, &mut schema, &windows)?;
+        trace(Stage::Window, rows.len());
+        let rows = sort_records(rows, &statement.order_by, &schema)?;
+        trace(Stage::Sort, rows.len());
+        ResultSet::new(statement, &schema, rows.iter())?
+    } else {
+        let matched = sort_records(matched, &statement.order_by, fields)?;
+        trace(Stage::Sort, matched.len());
+        ResultSet::new(statement, fields, matched.into_iter())?
+    };
+    trace(Stage::Output, result.records.len());
+    Ok(result)
+}
+
+fn group_rows(
+    matched: Vec<&Record>,
+    fields: &HashMap<String, String>,
+    statement: &SelectStatement,
+) -> Result<(Vec<Record>, HashMap<String, String>), CustomError> {
+    let mut schema = HashMap::new();
+    for field in &statement.group_by {
+        let field_type = fields
+            .get(field)
+            .ok_or_else(|| CustomError::UnknownField(field.to_string()))?;
+        schema.insert(field.to_string(), field_type.to_string());
+    }
+    for operand in statement.fields.iter().filter_map(|p| p.operand()) {
+        let mut used = Vec::new();
+        operand.fields(&mut used);
+        if let Some(field) = used.iter().find(|f| !statement.group_by.contains(f)) {
+            return Err(CustomError::InvalidAggregate(format!(
+                "{} must appear in GROUP BY or be used in an aggregate",
+                field
+            )));
+        }
+    }
+    let aggregates = statement.aggregates();
+    for aggregate in &aggregates {
+        schema.insert(aggregate.to_string(), aggregate.result_type(fields)?);
+    }
+    let having = bind_condition(&statement.having, &schema)?;
+
+    let rows = group_records(matched, &statement.group_by, &aggregates)
+        .into_iter()
+        .filter(|r| match &having {
+            Some(predicate) => predicate.matches(r),
+            None => true,
+        })
+        .collect();
+    Ok((rows, schema))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        custom_error::CustomError,
+        database::Database,
+        test_util::{self, run},
+    };
+
+    fn database() -> Database<i64> {
+        test_util::database(&[
+            "CREATE a KEY id FIELDS id: Int, n: Int, s: String, f: Float",
+            "CREATE b KEY id FIELDS id: Int, n: Int",
+            "INSERT INTO a VALUES (1, 2, 'hello', 2.0), (2, 3, 'x', 0.5)",
+        ])
+    }
+
+    #[test]
+    fn union_rejects_mismatched_column_types() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "SELECT id, n FROM a UNION SELECT s, s FROM a",
+        );
+        assert!(matches!(result, Err(CustomError::SetOperationError(_))));
+    }
+
+    #[test]
+    fn insert_select_rejects_mismatched_union() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "INSERT INTO b SELECT id, n FROM a WHERE id = 1 UNION SELECT s, s FROM a WHERE id = 2",
+        );
+        assert!(result.is_err());
+        assert_eq!(run(&mut database, "SELECT * FROM b").unwrap(), "");
+    }
+
+    #[test]
+    fn int_and_float_columns_combine_as_float() {
+        let mut database = database();
+        let result = run(&mut database, "SELECT n FROM a INTERSECT SELECT f FROM a").unwrap();
+        assert_eq!(result, "n=2");
+    }
+
+    #[test]
+    fn having_without_group_by_runs_as_one_group() {
+        let mut database = database();
+        let result = run(&mut database, "SELECT COUNT(*) FROM a HAVING COUNT(*) > 1");
+        assert_eq!(result.unwrap(), "COUNT(*)=2");
+        let result = run(&mut database, "SELECT COUNT(*) FROM a HAVING COUNT(*) > 2");
+        assert_eq!(result.unwrap(), "");
+        let result = run(&mut database, "SELECT id FROM a HAVING n > 100");
+        assert!(matches!(result, Err(CustomError::InvalidAggregate(_))));
+    }
+
+    #[test]
+    fn int_sum_overflow_does_not_panic() {
+        let mut database = database();
+        run(
+            &mut database,
+            "INSERT INTO b VALUES (1, 9223372036854775807), (2, 1)",
+        )
+        .unwrap();
+        let result = run(&mut database, "SELECT SUM(n) FROM b").unwrap();
+        assert_eq!(result, "SUM(n)=NULL");
+        let result = run(
+            &mut database,
+            "SELECT id, SUM(n) OVER (ORDER BY id) AS s FROM b",
+        )
+        .unwrap();
+        assert_eq!(result, "id=1, s=9223372036854775807 | id=2, s=NULL");
+    }
+
+    #[test]
+    fn null_values_are_shown_apart_from_missing_rows() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "SELECT MAX(n), COUNT(*) FROM a WHERE id > 100",
+        );
+        assert_eq!(result.unwrap(), "MAX(n)=NULL, COUNT(*)=0");
+        let result = run(&mut database, "SELECT n FROM a WHERE id > 100");
+        assert_eq!(result.unwrap(), "");
+        let result = run(&mut database, "SELECT id, NULL AS x FROM a WHERE id = 1");
+        assert_eq!(result.unwrap(), "id=1, x=NULL");
+    }
+
+    #[test]
+    fn recursive_cte_runs_until_a_round_adds_nothing() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "WITH RECURSIVE c(x) AS (SELECT n FROM a WHERE id = 1 \
+             UNION ALL SELECT x + 1 FROM c WHERE x < 5) SELECT * FROM c",
+        );
+        assert_eq!(result.unwrap(), "x=2 | x=3 | x=4 | x=5");
+    }
+
+    #[test]
+    fn recursive_union_stops_on_a_cycle() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "WITH RECURSIVE c(x) AS (SELECT n FROM a WHERE id = 1 \
+             UNION SELECT 3 - x FROM c) SELECT * FROM c",
+        );
+        assert_eq!(result.unwrap(), "x=2 | x=1");
+        let result = run(
+            &mut database,
+            "WITH RECURSIVE c(x) AS (SELECT n FROM a WHERE id = 1 \
+             UNION ALL SELECT 3 - x FROM c) SELECT * FROM c",
+        );
+        assert!(matches!(result, Err(CustomError::SubqueryError(_))));
+    }
+
+    #[test]
+    fn recursive_reference_inside_a_subquery() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "WITH RECURSIVE c(x) AS (SELECT n FROM a WHERE id = 1 \
+             UNION ALL SELECT n FROM a WHERE n IN (SELECT x + 1 FROM c)) SELECT * FROM c",
+        );
+        assert_eq!(result.unwrap(), "x=2 | x=3");
+    }
+
+    #[test]
+    fn recursive_step_keeps_the_anchor_types() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "WITH RECURSIVE c(x) AS (SELECT n FROM a WHERE id = 1 \
+             UNION ALL SELECT a.f FROM a JOIN c ON a.n = c.x) SELECT * FROM c",
+        );
+        assert!(matches!(result, Err(CustomError::SetOperationError(_))));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_and_and_than_or() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, g: String, n: Int",
+            "INSERT INTO t VALUES (1, 'x', 20), (2, 'y', 10), (3, 'x', 10), (4, 'y', 30)",
+        ]);
+        for (condition, expected) in [
+            ("g = 'x' OR g = 'y' AND n > 10", "id=1 | id=3 | id=4"),
+            ("(g = 'x' OR g = 'y') AND n > 10", "id=1 | id=4"),
+            ("NOT g = 'x' AND n = 10", "id=2"),
+            ("NOT (g = 'x' AND n = 10)", "id=1 | id=2 | id=4"),
+            ("NOT (g = 'x' OR n > 20)", "id=2"),
+        ] {
+            let select = format!("SELECT id FROM t WHERE {}", condition);
+            assert_eq!(
+                run(&mut database, &select).unwrap(),
+                expected,
+                "{}",
+                condition
+            );
+        }
+    }
+
+    #[test]
+    fn order_by_several_keys_with_directions() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, g: String, n: Int",
+            "INSERT INTO t VALUES (1, 'x', 20), (2, 'y', 10), (3, 'x', 10), (4, 'y', 30), (5, 'x', NULL)",
+        ]);
+        for (order, expected) in [
+            ("g, n", "id=5 | id=3 | id=1 | id=2 | id=4"),
+            ("g DESC, n ASC", "id=2 | id=4 | id=5 | id=3 | id=1"),
+            ("g ASC, n DESC", "id=1 | id=3 | id=5 | id=4 | id=2"),
+            ("n DESC, id DESC", "id=4 | id=1 | id=3 | id=2 | id=5"),
+        ] {
+            let select = format!("SELECT id FROM t ORDER BY {}", order);
+            assert_eq!(run(&mut database, &select).unwrap(), expected, "{}", order);
+        }
+        assert!(run(&mut database, "SELECT id FROM t ORDER BY zz").is_err());
+    }
+
+    #[test]
+    fn limit_and_offset_stop_the_scan_early() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int",
+            "INSERT INTO t VALUES (1, 20), (2, 10), (3, 10), (4, 30), (5, 40)",
+        ]);
+        for (select, expected) in [
+            ("SELECT id FROM t LIMIT 2", "id=1 | id=2"),
+            ("SELECT id FROM t LIMIT 2 OFFSET 3", "id=4 | id=5"),
+            ("SELECT id FROM t LIMIT 2 OFFSET 9", ""),
+            ("SELECT id FROM t LIMIT 0", ""),
+            ("SELECT id FROM t ORDER BY n DESC LIMIT 2", "id=5 | id=4"),
+        ] {
+            assert_eq!(run(&mut database, select).unwrap(), expected, "{}", select);
+        }
+
+        // Without ORDER BY the scan ends once OFFSET + LIMIT rows passed the filter.
+        let plan = run(
+            &mut database,
+            "EXPLAIN ANALYZE SELECT id FROM t WHERE n > 15 LIMIT 1 OFFSET 1",
+        )
+        .unwrap();
+        assert!(
+            plan.contains("Full scan on t, stops at the LIMIT  (actual rows=4,"),
+            "{}",
+            plan
+        );
+        let plan = run(
+            &mut database,
+            "EXPLAIN ANALYZE SELECT id FROM t ORDER BY n LIMIT 1",
+        )
+        .unwrap();
+        assert!(plan.contains("Full scan on t  (actual rows=5,"), "{}", plan);
+    }
+
+    #[test]
+    fn star_and_aliases_keep_the_written_column_order() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS s: String, id: Int, n: Int",
+            "INSERT INTO t VALUES ('a', 1, 10), ('b', 2, NULL)",
+        ]);
+        for (select, expected) in [
+            ("SELECT * FROM t", "s=a, id=1, n=10 | s=b, id=2, n=NULL"),
+            (
+                "SELECT n * 2 AS d, s AS name, id FROM t",
+                "d=20, name=a, id=1 | d=NULL, name=b, id=2",
+            ),
+            ("SELECT id, id AS k FROM t WHERE id = 1", "id=1, k=1"),
+            ("SELECT t.* FROM t WHERE id = 2", "s=b, id=2, n=NULL"),
+        ] {
+            assert_eq!(run(&mut database, select).unwrap(), expected, "{}", select);
+        }
+    }
+
+    #[test]
+    fn like_in_between_and_is_null() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int, s: String",
+            "INSERT INTO t VALUES (1, 20, 'apple'), (2, 10, 'Banana'), (3, 10, NULL), (4, 30, 'cherry'), (5, NULL, 'avocado')",
+        ]);
+        for (condition, expected) in [
+            ("s LIKE 'a%'", "id=1 | id=5"),
+            ("s LIKE '_pple'", "id=1"),
+            ("s ILIKE 'b%'", "id=2"),
+            ("n IN (10, 30)", "id=2 | id=3 | id=4"),
+            ("s IN ('apple', 'cherry')", "id=1 | id=4"),
+            ("n BETWEEN 10 AND 20", "id=1 | id=2 | id=3"),
+            ("n IS NULL", "id=5"),
+            ("s IS NOT NULL AND n IS NOT NULL", "id=1 | id=2 | id=4"),
+        ] {
+            let select = format!("SELECT id FROM t WHERE {}", condition);
+            assert_eq!(
+                run(&mut database, &select).unwrap(),
+                expected,
+                "{}",
+                condition
+            );
+        }
+        for condition in ["n IN (10, 'x')", "n BETWEEN 'a' AND 'b'", "n LIKE '1%'"] {
+            let select = format!("SELECT id FROM t WHERE {}", condition);
+            assert!(run(&mut database, &select).is_err(), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn regular_expressions_match_strings_only() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, n: Int, s: String",
+            "INSERT INTO t VALUES (1, 20, 'apple'), (2, 10, 'Banana'), (3, 10, NULL), (4, 30, 'cherry')",
+        ]);
+        for (condition, expected) in [
+            ("s ~ '^[a-c]'", "id=1 | id=4"),
+            ("s REGEXP 'an+a$'", "id=2"),
+            ("s ~ 'e'", "id=1 | id=4"),
+        ] {
+            let select = format!("SELECT id FROM t WHERE {}", condition);
+            assert_eq!(
+                run(&mut database, &select).unwrap(),
+                expected,
+                "{}",
+                condition
+            );
+        }
+        for condition in ["s ~ '('", "n ~ '1'"] {
+            let select = format!("SELECT id FROM t WHERE {}", condition);
+            assert!(run(&mut database, &select).is_err(), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn subqueries_in_where_and_from() {
+        let mut database = test_util::database(&[
+            "CREATE t KEY id FIELDS id: Int, g: String, n: Int",
+            "CREATE u KEY id FIELDS id: Int, t_id: Int, n: Int",
+            "INSERT INTO t VALUES (1, 'x', 20), (2, 'y', 10), (3, 'x', 10), (4, 'y', 30)",
+            "INSERT INTO u VALUES (1, 2, 10), (2, 2, 30), (3, 4, 10)",
+        ]);
+        for (select, expected) in [
+            (
+                "SELECT id FROM t WHERE id IN (SELECT t_id FROM u)",
+                "id=2 | id=4",
+            ),
+            (
+                "SELECT id FROM t WHERE id NOT IN (SELECT t_id FROM u)",
+                "id=1 | id=3",
+            ),
+            ("SELECT id FROM t WHERE n = (SELECT MAX(n) FROM u)", "id=4"),
+            (
+                "SELECT id FROM t WHERE EXISTS (SELECT id FROM u WHERE n = 20)",
+                "",
+            ),
+            (
+                "SELECT s.g, s.c FROM (SELECT g, COUNT(*) AS c FROM t WHERE n > 10 GROUP BY g) AS s",
+                "g=x, c=1 | g=y, c=1",
+            ),
+        ] {
+            assert_eq!(run(&mut database, select).unwrap(), expected, "{}", select);
+        }
+        for select in [
+            "SELECT id FROM t WHERE n = (SELECT n FROM u)",
+            "SELECT id FROM t WHERE id IN (SELECT t_id, n FROM u)",
+        ] {
+            assert!(matches!(
+                run(&mut database, select),
+                Err(CustomError::SubqueryError(_))
+            ));
+        }
+    }
+}
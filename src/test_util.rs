@@ -0,0 +1,36 @@
+use crate::{
+    command_history::CommandHistory,
+    custom_error::CustomError,
+    database::{Database, DatabaseKey},
+    handlers::handle_input,
+};
+
+// Runs one statement with a throwaway history.
+pub fn run<K: DatabaseKey>(database: &mut Database<K>, input: &str) -> Result<String, CustomError> {
+    run_logged(database, &mut CommandHistory::new(), input)
+}
+
+pub fn run_logged<K: DatabaseKey>(
+    database: &mut Database<K>,
+    history: &mut CommandHistory,
+    input: &str,
+) -> Result<String, CustomError> {
+    handle_input(input.to_string(), database, history)
+}
+
+// An Int-keyed database set up by `statements`, each of which has to succeed.
+pub fn database(statements: &[&str]) -> Database<i64> {
+    let mut database = Database::new();
+    for statement in statements {
+        if let Err(e) = run(&mut database, statement) {
+            panic!("{}: {}", statement, e);
+        }
+    }
+    database
+}
+
+// A new database that ran everything recorded in `history`.
+pub fn replay(history: &CommandHistory) -> Database<i64> {
+    let statements: Vec<&str> = history.list().iter().map(String::as_str).collect();
+    database(&statements)
+}
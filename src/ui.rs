@@ -2,7 +2,7 @@ use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
 };
 
 use crate::{
@@ -41,6 +41,8 @@ pub fn ui(frame: &mut Frame, app: &App) -> Result<(), CustomError> {
                 "Delete",
                 "Save As",
                 "Read From",
+                "Drop Table",
+                "Truncate Table",
             ];
 
             let list_items: Vec<ListItem> = items
@@ -353,7 +355,9 @@ pub fn ui(frame: &mut Frame, app: &App) -> Result<(), CustomError> {
                     }),
             );
             frame.render_widget(field_paragraph, chunks[0]);
-            frame.render_widget(list, chunks[1]);
+            // Scrolls the operators to the selected one when they do not all fit.
+            let mut state = ListState::default().with_selected(Some(app.current_index));
+            frame.render_stateful_widget(list, chunks[1], &mut state);
             frame.render_widget(value_paragraph, chunks[2]);
         }
         CurrentScreen::InputFilePath => {
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Aggregate, AggregateFunction, Literal, Operand, Window, WindowFunction},
+    custom_error::CustomError,
+    database::{Record, Value},
+    expression::{Scalar, bind_operand, bind_typed, comparable},
+    query::compare_keys,
+};
+
+enum Bound {
+    RowNumber,
+    Rank,
+    DenseRank,
+    // Reads `value` from the row `offset` places away in the partition, negative for LAG.
+    Shift {
+        value: Scalar,
+        offset: isize,
+        default: Scalar,
+    },
+    Aggregate {
+        aggregate: Aggregate,
+        integer: bool,
+    },
+}
+
+// Stores the value of each window in every record under the window's text, and its
+// type in `fields`, so the projection and ORDER BY read it like a column.
+pub fn apply_windows(
+    records: &mut [Record],
+    fields: &mut HashMap<String, String>,
+    windows: &[Window],
+) -> Result<(), CustomError> {
+    for window in windows {
+        let (values, typ) = window.evaluate(records, fields)?;
+        let name = window.to_string();
+        for (record, value) in records.iter_mut().zip(values) {
+            if let Some(value) = value {
+                record.values.insert(name.to_string(), value);
+            }
+        }
+        fields.insert(name, typ);
+    }
+    Ok(())
+}
+
+impl Window {
+    fn evaluate(
+        &self,
+        records: &[Record],
+        fields: &HashMap<String, String>,
+    ) -> Result<(Vec<Option<Value>>, String), CustomError> {
+        let (function, typ) = self.bind(fields)?;
+        let bind_all = |operands: &mut dyn Iterator<Item = &Operand>| {
+            operands
+                .map(|o| Ok(bind_operand(o, fields)?.0))
+                .collect::<Result<Vec<_>, CustomError>>()
+        };
+        let partition_by = bind_all(&mut self.partition_by.iter())?;
+        let order_by = bind_all(&mut self.order_by.iter().map(|o| &o.operand))?;
+        let descending = self
+            .order_by
+            .iter()
+            .map(|o| o.descending)
+            .collect::<Vec<_>>();
+
+        let evaluate_all = |scalars: &[Scalar]| {
+            records
+                .iter()
+                .map(|r| scalars.iter().map(|s| s.evaluate(r)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        let partitions = evaluate_all(&partition_by);
+        let keys = evaluate_all(&order_by);
+        let mut order = (0..records.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            partitions[a]
+                .cmp(&partitions[b])
+                .then_with(|| compare_keys(&keys[a], &keys[b], &descending))
+        });
+
+        let mut values = vec![None; records.len()];
+        for partition in order.chunk_by(|&a, &b| partitions[a] == partitions[b]) {
+            // Rows with equal ORDER BY keys are peers: they share a rank and a running
+            // total. Without ORDER BY the whole partition is one group of peers.
+            let peers = partition
+                .chunk_by(|&a, &b| keys[a] == keys[b])
+                .collect::<Vec<_>>();
+            function.fill(records, partition, &peers, &mut values);
+        }
+        Ok((values, typ))
+    }
+
+    fn bind(&self, fields: &HashMap<String, String>) -> Result<(Bound, String), CustomError> {
+        let arity = match self.function {
+            WindowFunction::Lag | WindowFunction::Lead => 1..=3,
+            _ => 0..=0,
+        };
+        if !arity.contains(&self.args.len()) {
+            return Err(CustomError::TypeError(format!(
+                "{} does not take {} arguments",
+                self.function.as_str(),
+                self.args.len()
+            )));
+        }
+
+        let bound = match &self.function {
+            WindowFunction::RowNumber => (Bound::RowNumber, "Int".to_string()),
+            WindowFunction::Rank => (Bound::Rank, "Int".to_string()),
+            WindowFunction::DenseRank => (Bound::DenseRank, "Int".to_string()),
+            WindowFunction::Aggregate(aggregate) => {
+                let typ = aggregate.result_type(fields)?;
+                let integer = typ == "Int";
+                let aggregate = aggregate.clone();
+                (Bound::Aggregate { aggregate, integer }, typ)
+            }
+            WindowFunction::Lag | WindowFunction::Lead => {
+                let (value, typ) = bind_operand(&self.args[0], fields)?;
+                let offset = match self.args.get(1) {
+                    None => Some(1),
+                    Some(Operand::Literal(Literal::Number(n))) => {
+                        n.parse::<isize>().ok().filter(|n| *n >= 0)
+                    }
+                    Some(_) => None,
+                };
+                let Some(offset) = offset else {
+                    return Err(CustomError::TypeError(format!(
+                        "{} offset must be a non-negative integer, {} is not",
+                        self.function.as_str(),
+                        self.args[1]
+                    )));
+                };
+                let offset = match self.function {
+                    WindowFunction::Lag => -offset,
+                    _ => offset,
+                };
+
+                let (value, default, typ) = match self.args.get(2) {
+                    None => (value, Scalar::Null, typ),
+                    Some(default) => {
+                        let (default, default_type) = bind_typed(default, fields, Some(&typ))?;
+                        if !comparable(&typ, &default_type) {
+                            return Err(CustomError::TypeError(format!(
+                                "{} default {} ({}) does not match {} ({})",
+                                self.function.as_str(),
+                                self.args[2],
+                                default_type,
+                                self.args[0],
+                                typ
+                            )));
+                        }
+                        // A mix of Int and Float becomes Float, as in COALESCE.
+                        match typ == default_type {
+                            true => (value, default, typ),
+                            false => (
+                                Scalar::ToFloat(Box::new(value)),
+                                Scalar::ToFloat(Box::new(default)),
+                                "Float".to_string(),
+                            ),
+                        }
+                    }
+                };
+                let shift = Bound::Shift {
+                    value,
+                    offset,
+                    default,
+                };
+                (shift, typ)
+            }
+        };
+        Ok(bound)
+    }
+}
+
+impl Bound {
+    // `partition` holds record indices in window order, `peers` the same split into
+    // groups of equal ORDER BY keys.
+    fn fill(
+        &self,
+        records: &[Record],
+        partition: &[usize],
+        peers: &[&[usize]],
+        values: &mut [Option<Value>],
+    ) {
+        match self {
+            Bound::RowNumber => {
+                for (position, &index) in partition.iter().enumerate() {
+                    values[index] = Some(Value::Int(position as i64 + 1));
+                }
+            }
+            Bound::Rank | Bound::DenseRank => {
+                let mut position = 0;
+                for (group_number, group) in peers.iter().enumerate() {
+                    let rank = match self {
+                        Bound::Rank => position + 1,
+                        _ => group_number + 1,
+                    };
+                    for &index in group.iter() {
+                        values[index] = Some(Value::Int(rank as i64));
+                    }
+                    position += group.len();
+                }
+            }
+            Bound::Shift {
+                value,
+                offset,
+                default,
+            } => {
+                for (position, &index) in partition.iter().enumerate() {
+                    let target = position
+                        .checked_add_signed(*offset)
+                        .and_then(|t| partition.get(t));
+                    values[index] = match target {
+                        Some(&t) => value.evaluate(&records[t]),
+                        None => default.evaluate(&records[index]),
+                    };
+                }
+            }
+            // The frame runs from the start of the partition through the current row's
+            // last peer, so totals are folded in one peer group at a time.
+            Bound::Aggregate { aggregate, integer } => {
+                let mut running = Running::default();
+                for group in peers {
+                    for &index in group.iter() {
+                        match &aggregate.field {
+                            Some(field) => {
+                                if let Some(value) = records[index].values.get(field) {
+                                    running.add(value);
+                                }
+                            }
+                            None => running.count += 1,
+                        }
+                    }
+                    let result = running.result(aggregate.function, *integer);
+                    for &index in group.iter() {
+                        values[index] = result.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Running {
+    count: i64,
+    int_sum: i64,
+    overflowed: bool,
+    float_sum: f64,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+impl Running {
+    fn add(&mut self, value: &Value) {
+        self.count += 1;
+        if let Value::Int(i) = value {
+            match self.int_sum.checked_add(*i) {
+                Some(sum) => self.int_sum = sum,
+                None => self.overflowed = true,
+            }
+        }
+        self.float_sum += value.as_f64().unwrap_or_default();
+        if self.min.as_ref().is_none_or(|m| value < m) {
+            self.min = Some(value.clone());
+        }
+        if self.max.as_ref().is_none_or(|m| value > m) {
+            self.max = Some(value.clone());
+        }
+    }
+
+    // Matches `Aggregate::evaluate` over the same rows.
+    fn result(&self, function: AggregateFunction, integer: bool) -> Option<Value> {
+        match function {
+            AggregateFunction::Count => Some(Value::Int(self.count)),
+            AggregateFunction::Sum | AggregateFunction::Avg if self.count == 0 => None,
+            AggregateFunction::Sum if integer && self.overflowed => None,
+            AggregateFunction::Sum if integer => Some(Value::Int(self.int_sum)),
+            AggregateFunction::Sum => Some(Value::Float(self.float_sum)),
+            AggregateFunction::Avg => Some(Value::Float(self.float_sum / self.count as f64)),
+            AggregateFunction::Min => self.min.clone(),
+            AggregateFunction::Max => self.max.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        custom_error::CustomError,
+        database::Database,
+        test_util::{self, run},
+    };
+
+    fn database() -> Database<i64> {
+        test_util::database(&[
+            "CREATE a KEY id FIELDS id: Int, g: String, n: Int",
+            "INSERT INTO a VALUES (1, 'x', 10), (2, 'x', 20), (3, 'x', 20), (4, 'y', 5), (5, 'y', NULL)",
+        ])
+    }
+
+    #[test]
+    fn peers_share_a_rank() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "SELECT id, RANK() OVER (ORDER BY n DESC) AS r, \
+             DENSE_RANK() OVER (ORDER BY n DESC) AS d, \
+             ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) AS rn FROM a",
+        );
+        assert_eq!(
+            result.unwrap(),
+            "id=1, r=3, d=2, rn=1 | id=2, r=1, d=1, rn=2 | id=3, r=1, d=1, rn=3 \
+             | id=4, r=4, d=3, rn=1 | id=5, r=5, d=4, rn=2"
+        );
+    }
+
+    // With ORDER BY the frame ends at the current row's last peer, without it the
+    // whole partition is one peer group.
+    #[test]
+    fn running_totals_include_all_peers() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "SELECT id, SUM(n) OVER (PARTITION BY g ORDER BY n) AS s, \
+             COUNT(*) OVER (ORDER BY g) AS c, \
+             MAX(n) OVER (PARTITION BY g) AS m FROM a",
+        );
+        assert_eq!(
+            result.unwrap(),
+            "id=1, s=10, c=3, m=20 | id=2, s=50, c=3, m=20 | id=3, s=50, c=3, m=20 \
+             | id=4, s=5, c=5, m=5 | id=5, s=NULL, c=5, m=5"
+        );
+    }
+
+    #[test]
+    fn lag_and_lead_fall_back_to_their_default() {
+        let mut database = database();
+        let result = run(
+            &mut database,
+            "SELECT id, LAG(n) OVER (ORDER BY id) AS p, \
+             LEAD(n, 2, 0) OVER (PARTITION BY g ORDER BY id) AS q FROM a",
+        );
+        assert_eq!(
+            result.unwrap(),
+            "id=1, p=NULL, q=20 | id=2, p=10, q=0 | id=3, p=20, q=0 | id=4, p=20, q=0 | id=5, p=5, q=0"
+        );
+        let result = run(&mut database, "SELECT LAG(n, -1) OVER (ORDER BY id) FROM a");
+        assert!(matches!(result, Err(CustomError::TypeError(_))));
+        let result = run(
+            &mut database,
+            "SELECT LAG(n, 1, 'none') OVER (ORDER BY id) FROM a",
+        );
+        assert!(result.is_err());
+    }
+}